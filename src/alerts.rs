@@ -0,0 +1,573 @@
+use crate::config::validate_instrument;
+use crate::models::{parse_decimal, TkrData};
+use anyhow::{anyhow, Context, Result};
+use cli_log::*;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const LOG_LEN: usize = 200;
+
+/// Ticker field a rule watches
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    Last,
+    Bid,
+    Ask,
+    Mid,
+    High,
+    Low,
+    Volume,
+    Change, // 24h, in %
+    Spread, // bid/ask, in bps of the mid
+}
+
+impl Metric {
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "last" | "price" => Ok(Metric::Last),
+            "bid" => Ok(Metric::Bid),
+            "ask" => Ok(Metric::Ask),
+            "mid" => Ok(Metric::Mid),
+            "high" => Ok(Metric::High),
+            "low" => Ok(Metric::Low),
+            "volume" => Ok(Metric::Volume),
+            "change" => Ok(Metric::Change),
+            "spread" => Ok(Metric::Spread),
+            _ => Err(anyhow!("unknown field {:?}", name)),
+        }
+    }
+
+    pub fn value(self, data: &TkrData) -> Option<Decimal> {
+        match self {
+            Metric::Last => data.last,
+            Metric::Bid => data.bid,
+            Metric::Ask => data.ask,
+            Metric::Mid => data.mid(),
+            Metric::High => data.high,
+            Metric::Low => data.low,
+            Metric::Volume => data.volume,
+            Metric::Change => data.change.map(|c| c * Decimal::ONE_HUNDRED),
+            Metric::Spread => {
+                let (bid, ask) = (data.bid?, data.ask?);
+                let mid = (bid + ask) / Decimal::TWO;
+                (!mid.is_zero()).then(|| (ask - bid) / mid * Decimal::from(10_000))
+            }
+        }
+    }
+
+    fn unit(self) -> &'static str {
+        match self {
+            Metric::Change => "%",
+            Metric::Spread => " bps",
+            _ => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    Above(Metric, Decimal),
+    Below(Metric, Decimal),
+    Stale(i64), // millis without a ticker update
+}
+
+/// A parsed rule such as "BTCUSD-PERP last > 70000", "ETHUSD-PERP 24h change
+/// < -5%", "BTCUSD-PERP spread > 10 bps" or "BTCUSD-PERP no update for 30s".
+/// Saved as its text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rule {
+    text: String,
+    pub tkr: String,
+    pub condition: Condition,
+}
+
+impl Rule {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut words = text.split_whitespace();
+        let tkr = words
+            .next()
+            .ok_or_else(|| anyhow!("empty rule"))?
+            .to_ascii_uppercase();
+        validate_instrument(&tkr)?;
+        let words: Vec<String> = words
+            .map(|w| w.to_ascii_lowercase())
+            .filter(|w| w != "24h")
+            .collect();
+        let words: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+
+        let condition = match words.as_slice() {
+            ["no", "update", "for", time] | ["no", "updates", "for", time] | ["stale", time] => {
+                Condition::Stale(parse_millis(time)?)
+            }
+            [metric, op, value @ ..] if !value.is_empty() => {
+                let metric = Metric::parse(metric)?;
+                let value = value.concat();
+                let value = value
+                    .strip_suffix(metric.unit().trim())
+                    .filter(|_| !metric.unit().is_empty())
+                    .unwrap_or(&value);
+                let threshold = parse_decimal(value)?;
+                match *op {
+                    ">" => Condition::Above(metric, threshold),
+                    "<" => Condition::Below(metric, threshold),
+                    _ => return Err(anyhow!("expected > or <, got {:?}", op)),
+                }
+            }
+            _ => {
+                return Err(anyhow!(
+                    "expected e.g. \"{} last > 70000\" or \"{} no update for 30s\"",
+                    tkr,
+                    tkr
+                ))
+            }
+        };
+        Ok(Self {
+            text: format!("{} {}", tkr, words.join(" ")),
+            tkr,
+            condition,
+        })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl TryFrom<String> for Rule {
+    type Error = anyhow::Error;
+
+    fn try_from(text: String) -> Result<Self> {
+        Rule::parse(&text)
+    }
+}
+
+impl From<Rule> for String {
+    fn from(rule: Rule) -> Self {
+        rule.text
+    }
+}
+
+/// "30s", "5m" or plain seconds
+fn parse_millis(time: &str) -> Result<i64> {
+    let (number, unit) = match time.strip_suffix('m') {
+        Some(minutes) => (minutes, 60_000),
+        None => (time.strip_suffix('s').unwrap_or(time), 1_000),
+    };
+    let number: i64 = number
+        .parse()
+        .map_err(|_| anyhow!("invalid duration {:?}", time))?;
+    if number <= 0 {
+        return Err(anyhow!("duration must be positive: {:?}", time));
+    }
+    Ok(number * unit)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub rule: Rule,
+    pub armed: bool, // switched on; a disarmed rule isn't evaluated
+    pub fired: bool, // fired and the condition still holds, so it won't fire again yet
+    pub fired_at: Option<i64>,
+}
+
+/// An alert going off
+#[derive(Debug, Clone, Serialize)]
+pub struct Fired {
+    pub time: i64, // unix millis
+    pub tkr: String,
+    pub rule: String,
+    pub value: String, // what set it off, e.g. "70012.5" or "31s"
+}
+
+/// Alert rules with their state, saved to disk whenever it changes, and a
+/// log of the alerts fired this session
+pub struct Alerts {
+    path: Option<PathBuf>,
+    pub alerts: Vec<Alert>,
+    pub log: VecDeque<Fired>, // newest first
+    last_seen: HashMap<String, i64>,
+    started: i64,
+}
+
+impl Alerts {
+    /// `$XDG_DATA_HOME/crypto_tui/alerts.json` or the platform equivalent
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("crypto_tui").join("alerts.json"))
+    }
+
+    /// Loads the saved alerts and adds any of `rules` not among them yet
+    pub fn load(path: Option<PathBuf>, rules: &[String], now: i64) -> Result<Self> {
+        let alerts = match path.as_deref() {
+            Some(path) if path.exists() => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("reading alerts {}", path.display()))?;
+                serde_json::from_str(&text)
+                    .with_context(|| format!("parsing alerts {}", path.display()))?
+            }
+            _ => Vec::new(),
+        };
+        let mut alerts = Self {
+            path,
+            alerts,
+            log: VecDeque::new(),
+            last_seen: HashMap::new(),
+            started: now,
+        };
+        for rule in rules {
+            let rule = Rule::parse(rule).context("in alerts.rules")?;
+            if !alerts.alerts.iter().any(|alert| alert.rule == rule) {
+                alerts.push(rule);
+            }
+        }
+        Ok(alerts)
+    }
+
+    /// Keeps the state on disk as it is, e.g. while replaying a recording
+    pub fn without_saving(mut self) -> Self {
+        self.path = None;
+        self
+    }
+
+    fn push(&mut self, rule: Rule) {
+        self.alerts.push(Alert {
+            rule,
+            armed: true,
+            fired: false,
+            fired_at: None,
+        });
+    }
+
+    pub fn add(&mut self, text: &str) -> Result<()> {
+        let rule = Rule::parse(text)?;
+        if self.alerts.iter().any(|alert| alert.rule == rule) {
+            return Err(anyhow!("already have {}", rule));
+        }
+        self.push(rule);
+        self.save();
+        Ok(())
+    }
+
+    /// Removes alert `n`, counted from 1 as listed
+    pub fn remove(&mut self, n: usize) -> Result<()> {
+        self.index(n)?;
+        self.alerts.remove(n - 1);
+        self.save();
+        Ok(())
+    }
+
+    /// Arms or disarms alert `n`; rearming lets a condition that still
+    /// holds fire again
+    pub fn set_armed(&mut self, n: usize, armed: bool) -> Result<()> {
+        let i = self.index(n)?;
+        let alert = &mut self.alerts[i];
+        alert.armed = armed;
+        alert.fired = false;
+        self.save();
+        Ok(())
+    }
+
+    fn index(&self, n: usize) -> Result<usize> {
+        if n == 0 || n > self.alerts.len() {
+            return Err(anyhow!("no alert #{}", n));
+        }
+        Ok(n - 1)
+    }
+
+    /// Evaluates the rules of `tkr` against an update received at `now`
+    pub fn check(&mut self, tkr: &str, data: &TkrData, now: i64) -> Vec<Fired> {
+        self.last_seen.insert(tkr.to_string(), now);
+        let mut fired = Vec::new();
+        let mut changed = false;
+        for alert in self.alerts.iter_mut() {
+            if !alert.armed || alert.rule.tkr != tkr {
+                continue;
+            }
+            let (metric, threshold, above) = match alert.rule.condition {
+                Condition::Above(metric, threshold) => (metric, threshold, true),
+                Condition::Below(metric, threshold) => (metric, threshold, false),
+                Condition::Stale(_) => {
+                    changed |= std::mem::take(&mut alert.fired);
+                    continue;
+                }
+            };
+            // Fields missing from an update leave the state as it was
+            let Some(value) = metric.value(data) else {
+                continue;
+            };
+            let holds = if above {
+                value > threshold
+            } else {
+                value < threshold
+            };
+            changed |= update(alert, holds, now, &mut fired, || {
+                format!("{}{}", value.round_dp(4).normalize(), metric.unit())
+            });
+        }
+        self.finish(changed, fired)
+    }
+
+    /// Evaluates the "no update" rules; called on a timer
+    pub fn check_stale(&mut self, now: i64) -> Vec<Fired> {
+        let mut fired = Vec::new();
+        let mut changed = false;
+        for alert in self.alerts.iter_mut() {
+            let Condition::Stale(limit) = alert.rule.condition else {
+                continue;
+            };
+            if !alert.armed {
+                continue;
+            }
+            let seen = self
+                .last_seen
+                .get(&alert.rule.tkr)
+                .copied()
+                .unwrap_or(self.started);
+            let age = now - seen;
+            changed |= update(alert, age >= limit, now, &mut fired, || {
+                format!("{}s", age / 1_000)
+            });
+        }
+        self.finish(changed, fired)
+    }
+
+    fn finish(&mut self, changed: bool, fired: Vec<Fired>) -> Vec<Fired> {
+        for event in fired.iter() {
+            info!("Alert {}: {}", event.rule, event.value);
+            self.log.push_front(event.clone());
+        }
+        self.log.truncate(LOG_LEN);
+        if changed {
+            self.save();
+        }
+        fired
+    }
+
+    fn save(&self) {
+        let Some(path) = self.path.as_deref() else {
+            return;
+        };
+        if let Err(e) = write_alerts(path, &self.alerts) {
+            debug!("Failed to save alerts: {}", e);
+        }
+    }
+}
+
+/// Fires `alert` when its condition starts to hold and clears it once it
+/// stops; returns whether its state changed
+fn update(
+    alert: &mut Alert,
+    holds: bool,
+    now: i64,
+    fired: &mut Vec<Fired>,
+    value: impl FnOnce() -> String,
+) -> bool {
+    if holds == alert.fired {
+        return false;
+    }
+    alert.fired = holds;
+    if holds {
+        alert.fired_at = Some(now);
+        fired.push(Fired {
+            time: now,
+            tkr: alert.rule.tkr.clone(),
+            rule: alert.rule.to_string(),
+            value: value(),
+        });
+    }
+    true
+}
+
+fn write_alerts(path: &Path, alerts: &[Alert]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(alerts)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TKR: &str = "BTCUSD-PERP";
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("crypto_tui-{}-{}", std::process::id(), name))
+    }
+
+    fn last(price: i64) -> TkrData {
+        TkrData {
+            last: Some(Decimal::from(price)),
+            ..TkrData::default()
+        }
+    }
+
+    fn alerts(rules: &[&str]) -> Alerts {
+        let rules: Vec<String> = rules.iter().map(|rule| rule.to_string()).collect();
+        Alerts::load(None, &rules, 0).unwrap()
+    }
+
+    #[test]
+    fn parses_each_form() {
+        let condition = |text: &str| Rule::parse(text).unwrap().condition;
+        assert_eq!(
+            condition("BTCUSD-PERP last > 70000"),
+            Condition::Above(Metric::Last, Decimal::from(70_000))
+        );
+        assert_eq!(
+            condition("ETHUSD-PERP 24h change < -5%"),
+            Condition::Below(Metric::Change, Decimal::from(-5))
+        );
+        assert_eq!(
+            condition("BTCUSD-PERP spread > 10 bps"),
+            Condition::Above(Metric::Spread, Decimal::from(10))
+        );
+        assert_eq!(
+            condition("BTCUSD-PERP no update for 30s"),
+            Condition::Stale(30_000)
+        );
+        assert_eq!(condition("BTCUSD-PERP stale 5m"), Condition::Stale(300_000));
+
+        // Saved as typed, give or take case and "24h"
+        let rule = Rule::parse("ethusd-perp 24h Change < -5%").unwrap();
+        assert_eq!(rule.tkr, "ETHUSD-PERP");
+        assert_eq!(rule.to_string(), "ETHUSD-PERP change < -5%");
+        assert_eq!(Rule::parse(&rule.to_string()).unwrap(), rule);
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        for text in [
+            "",
+            "BTCUSD-PERP",
+            "BTC/USD last > 1",
+            "BTCUSD-PERP last >",
+            "BTCUSD-PERP last >= 70000",
+            "BTCUSD-PERP colour > 1",
+            "BTCUSD-PERP last > lots",
+            "BTCUSD-PERP change < -5bps",
+            "BTCUSD-PERP no update for 0s",
+            "BTCUSD-PERP no update for soon",
+        ] {
+            assert!(Rule::parse(text).is_err(), "{:?} parsed", text);
+        }
+    }
+
+    #[test]
+    fn fires_once_per_crossing() {
+        let mut alerts = alerts(&["BTCUSD-PERP last > 70000"]);
+        let mut fire = |price, now| alerts.check(TKR, &last(price), now).len();
+        assert_eq!(fire(69_000, 1), 0);
+        assert_eq!(fire(70_001, 2), 1);
+        assert_eq!(fire(70_500, 3), 0);
+        assert_eq!(fire(70_000, 4), 0); // back, not past
+        assert_eq!(fire(70_100, 5), 1);
+
+        // Other instruments and missing fields leave it as it was
+        assert!(alerts.check("ETHUSD-PERP", &last(1), 6).is_empty());
+        assert!(alerts.check(TKR, &TkrData::default(), 7).is_empty());
+        assert!(alerts.alerts[0].fired);
+        assert_eq!(alerts.alerts[0].fired_at, Some(5));
+
+        let log: Vec<&str> = alerts.log.iter().map(|f| f.value.as_str()).collect();
+        assert_eq!(log, ["70100", "70001"]);
+        assert_eq!(alerts.log[0].rule, "BTCUSD-PERP last > 70000");
+    }
+
+    #[test]
+    fn fires_on_spread_and_change() {
+        let mut alerts = alerts(&["BTCUSD-PERP spread > 10 bps", "BTCUSD-PERP change < -5%"]);
+        let data = TkrData {
+            bid: Some(Decimal::from(9_990)),
+            ask: Some(Decimal::from(10_010)),
+            change: Some(Decimal::new(-6, 2)),
+            ..TkrData::default()
+        };
+        let values: Vec<String> = alerts
+            .check(TKR, &data, 1)
+            .into_iter()
+            .map(|f| f.value)
+            .collect();
+        assert_eq!(values, ["20 bps", "-6%"]);
+    }
+
+    #[test]
+    fn disarmed_alerts_stay_quiet() {
+        let mut alerts = alerts(&["BTCUSD-PERP last > 70000"]);
+        alerts.set_armed(1, false).unwrap();
+        assert!(alerts.check(TKR, &last(71_000), 1).is_empty());
+        // Rearming lets a condition that still holds fire again
+        alerts.set_armed(1, true).unwrap();
+        assert_eq!(alerts.check(TKR, &last(71_000), 2).len(), 1);
+        assert!(alerts.set_armed(2, true).is_err());
+    }
+
+    #[test]
+    fn fires_when_updates_stop() {
+        let mut alerts = alerts(&["BTCUSD-PERP no update for 30s"]);
+        assert!(alerts.check_stale(29_999).is_empty());
+        let fired = alerts.check_stale(30_000);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].value, "30s");
+        assert!(alerts.check_stale(40_000).is_empty());
+
+        // An update clears it, and the clock starts again from there
+        alerts.check(TKR, &last(1), 41_000);
+        assert!(!alerts.alerts[0].fired);
+        assert!(alerts.check_stale(70_999).is_empty());
+        assert_eq!(alerts.check_stale(71_000).len(), 1);
+    }
+
+    #[test]
+    fn saves_the_fired_state() {
+        let path = temp_path("alerts.json");
+        let rules = ["BTCUSD-PERP last > 70000".to_string()];
+        let mut alerts = Alerts::load(Some(path.clone()), &rules, 0).unwrap();
+        alerts.add("ETHUSD-PERP no update for 1m").unwrap();
+        alerts.set_armed(2, false).unwrap();
+        alerts.check(TKR, &last(71_000), 5);
+
+        let loaded = Alerts::load(Some(path.clone()), &rules, 0).unwrap();
+        fs::remove_file(&path).unwrap();
+        let state: Vec<(String, bool, bool, Option<i64>)> = loaded
+            .alerts
+            .iter()
+            .map(|a| (a.rule.to_string(), a.armed, a.fired, a.fired_at))
+            .collect();
+        assert_eq!(
+            state,
+            [
+                ("BTCUSD-PERP last > 70000".to_string(), true, true, Some(5)),
+                (
+                    "ETHUSD-PERP no update for 1m".to_string(),
+                    false,
+                    false,
+                    None
+                ),
+            ]
+        );
+        // Still fired, so the same price doesn't go off again after a restart
+        let mut loaded = loaded;
+        assert!(loaded.check(TKR, &last(71_000), 6).is_empty());
+    }
+
+    #[test]
+    fn without_saving_writes_nothing() {
+        let path = temp_path("alerts-unsaved.json");
+        let mut alerts = Alerts::load(Some(path.clone()), &[], 0)
+            .unwrap()
+            .without_saving();
+        alerts.add("BTCUSD-PERP last > 70000").unwrap();
+        alerts.check(TKR, &last(71_000), 1);
+        alerts.set_armed(1, false).unwrap();
+        alerts.remove(1).unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::models::Instrument;
+use anyhow::{anyhow, Result};
+use cli_log::*;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Deserialize)]
+struct InstrumentsResponse {
+    code: i64,
+    result: InstrumentsResult,
+}
+
+#[derive(Deserialize)]
+struct InstrumentsResult {
+    data: Vec<Instrument>,
+}
+
+/// Where the raw get-instruments response is cached between runs
+pub fn cache_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("crypto_tui").join("instruments.json"))
+}
+
+/// Parses a `public/get-instruments` response body
+pub fn parse(text: &str) -> Result<Vec<Instrument>> {
+    let resp: InstrumentsResponse = serde_json::from_str(text)?;
+    if resp.code != 0 {
+        return Err(anyhow!("get-instruments returned code {}", resp.code));
+    }
+    Ok(resp.result.data)
+}
+
+/// Reads a saved response, e.g. the cache or `fixtures/get-instruments.json`
+pub fn from_file(path: &Path) -> Result<Vec<Instrument>> {
+    parse(&fs::read_to_string(path)?)
+}
+
+pub async fn fetch(url: &str) -> Result<String> {
+    let resp = reqwest::get(url).await?.error_for_status()?;
+    Ok(resp.text().await?)
+}
+
+/// Loads the catalogue from a fresh cache, otherwise from the exchange,
+/// falling back to a stale cache when the exchange can't be reached.
+pub async fn load(url: &str) -> Result<Vec<Instrument>> {
+    let cache = cache_path();
+    if let Some(path) = cache.as_deref() {
+        let age = fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        if matches!(age, Some(age) if age < CACHE_TTL) {
+            match from_file(path) {
+                Ok(instruments) => return Ok(instruments),
+                Err(e) => debug!("Ignoring bad instrument cache: {}", e),
+            }
+        }
+    }
+
+    let fetched = match fetch(url).await {
+        Ok(text) => parse(&text).map(|instruments| (text, instruments)),
+        Err(e) => Err(e),
+    };
+    match (fetched, cache) {
+        (Ok((text, instruments)), Some(path)) => {
+            if let Err(e) = write_cache(&path, &text) {
+                debug!("Failed to write instrument cache: {}", e);
+            }
+            Ok(instruments)
+        }
+        (Ok((_, instruments)), None) => Ok(instruments),
+        (Err(e), Some(path)) if path.exists() => {
+            debug!("Fetching instruments failed, using stale cache: {}", e);
+            from_file(&path)
+        }
+        (Err(e), _) => Err(e),
+    }
+}
+
+fn write_cache(path: &Path, text: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)?;
+    Ok(())
+}
+
+/// Best fuzzy matches for `query` against symbol and display name
+pub fn search<'a>(instruments: &'a [Instrument], query: &str, limit: usize) -> Vec<&'a Instrument> {
+    let mut matches: Vec<(i64, &Instrument)> = instruments
+        .iter()
+        .filter_map(|inst| {
+            let score =
+                fuzzy_score(query, &inst.symbol).max(fuzzy_score(query, &inst.display_name));
+            score.map(|score| (score, inst))
+        })
+        .collect();
+
+    matches.sort_by(|(score_a, a), (score_b, b)| {
+        score_b
+            .cmp(score_a)
+            .then(b.tradable.cmp(&a.tradable))
+            .then(a.symbol.len().cmp(&b.symbol.len()))
+            .then(a.symbol.cmp(&b.symbol))
+    });
+    matches
+        .into_iter()
+        .take(limit)
+        .map(|(_, inst)| inst)
+        .collect()
+}
+
+/// Subsequence match scored higher for prefix, consecutive and word start hits
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let chars: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last: Option<usize> = None;
+
+    for q in query.chars() {
+        let i = pos
+            + chars[pos..]
+                .iter()
+                .position(|c| c.eq_ignore_ascii_case(&q))?;
+        score += 1;
+        if i == 0 {
+            score += 3;
+        } else if last == Some(i - 1) || !chars[i - 1].is_alphanumeric() {
+            score += 2;
+        }
+        last = Some(i);
+        pos = i + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/get-instruments.json");
+
+    fn symbols(matches: Vec<&Instrument>) -> Vec<&str> {
+        matches.iter().map(|inst| inst.symbol.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_get_instruments() {
+        let instruments = from_file(Path::new(FIXTURE)).unwrap();
+        assert_eq!(instruments.len(), 10);
+        let btc = &instruments[0];
+        assert_eq!(btc.symbol, "BTCUSD-PERP");
+        assert_eq!(btc.inst_type, "PERPETUAL_SWAP");
+        assert_eq!(btc.base_ccy, "BTC");
+        assert!(btc.tradable);
+        assert!(instruments.iter().any(|inst| !inst.tradable));
+
+        assert!(parse(
+            r#"{"id":1,"method":"public/get-instruments","code":10004,"result":{"data":[]}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn searches_symbols_and_names() {
+        let instruments = from_file(Path::new(FIXTURE)).unwrap();
+        assert_eq!(symbols(search(&instruments, "btcperp", 5)), ["BTCUSD-PERP"]);
+        // Equal scores go to the shorter symbol
+        assert_eq!(
+            symbols(search(&instruments, "eth", 5)),
+            ["ETH_USD", "ETHUSD-PERP"]
+        );
+        // Display names match too, up to the limit
+        let perps = symbols(search(&instruments, "perpetual", 3));
+        assert_eq!(perps.len(), 3);
+        assert!(perps.iter().all(|symbol| symbol.ends_with("-PERP")));
+        // Expired contracts go after the tradable ones
+        let btc = symbols(search(&instruments, "btc", 10));
+        assert_eq!(btc.last(), Some(&"BTCUSD-241227"));
+        assert!(search(&instruments, "zzz", 5).is_empty());
+    }
+}
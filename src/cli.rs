@@ -0,0 +1,254 @@
+use crate::config::{self, Config, Theme};
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Real-time crypto.com market data in the terminal
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Instruments to watch when no subcommand is given
+    #[arg(value_parser = parse_instrument)]
+    pub instruments: Vec<String>,
+
+    #[command(flatten)]
+    pub global: GlobalArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct GlobalArgs {
+    /// Market data venue: crypto.com or binance
+    #[arg(long, global = true)]
+    pub venue: Option<String>,
+
+    /// Also stream VENUE for the price comparison view; repeatable
+    #[arg(long, global = true, value_name = "VENUE")]
+    pub compare: Vec<String>,
+
+    /// Websocket endpoint of the venue, overrides the config file
+    #[arg(long, global = true)]
+    pub url: Option<String>,
+
+    /// Config file [default: ~/.config/crypto_tui/config.toml]
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Ticker and trade history kept per instrument
+    #[arg(long, global = true)]
+    pub capacity: Option<usize>,
+
+    /// Log to crypto_tui.log at this level
+    #[arg(long, global = true, value_parser = ["off", "error", "warn", "info", "debug", "trace"])]
+    pub log_level: Option<String>,
+
+    /// Colour theme: catppuccin, classic or mono
+    #[arg(long, global = true)]
+    pub theme: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Interactive dashboard (default)
+    Tui {
+        #[arg(value_parser = parse_instrument)]
+        instruments: Vec<String>,
+        /// Also record received frames to FILE (gzip ndjson)
+        #[arg(long, value_name = "FILE")]
+        record: Option<PathBuf>,
+    },
+    /// Print market data as newline-delimited JSON without a UI
+    Stream {
+        #[arg(value_parser = parse_instrument)]
+        instruments: Vec<String>,
+    },
+    /// Record raw market data frames to a gzip ndjson file until Ctrl-C
+    Record {
+        /// Output file, e.g. session.ndjson.gz
+        #[arg(short, long)]
+        out: PathBuf,
+        #[arg(value_parser = parse_instrument)]
+        instruments: Vec<String>,
+    },
+    /// Play a recording back in the dashboard
+    Replay {
+        file: PathBuf,
+        /// Playback speed as a multiple of the recorded pace
+        #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+        speed: f64,
+        /// Advance one frame per press of the step key instead of in time
+        #[arg(long, conflicts_with = "speed")]
+        step: bool,
+        /// Instruments to show [default: all in the recording]
+        #[arg(value_parser = parse_instrument)]
+        instruments: Vec<String>,
+    },
+    /// List instruments from the exchange, optionally fuzzy matching QUERY
+    ListInstruments {
+        query: Option<String>,
+        /// Most matches to print
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+}
+
+impl Cli {
+    /// The subcommand given, else the dashboard on the bare instruments
+    pub fn into_command(self) -> Command {
+        self.command.unwrap_or(Command::Tui {
+            instruments: self.instruments,
+            record: None,
+        })
+    }
+}
+
+impl GlobalArgs {
+    /// Applies command line overrides on top of the loaded config
+    pub fn apply(&self, config: &mut Config) -> Result<()> {
+        if let Some(venue) = &self.venue {
+            config.endpoint.venue = venue.clone();
+        }
+        if !self.compare.is_empty() {
+            config.endpoint.compare = self.compare.clone();
+        }
+        if let Some(url) = &self.url {
+            *config.endpoint.url_mut() = url.clone();
+        }
+        if let Some(capacity) = self.capacity {
+            config.buffers.tickers = capacity;
+            config.buffers.trades = capacity;
+        }
+        if let Some(name) = &self.theme {
+            config.theme = Theme::preset(name)?;
+        }
+        config.validate()
+    }
+}
+
+fn parse_instrument(arg: &str) -> Result<String> {
+    config::validate_instrument(arg)?;
+    Ok(arg.to_string())
+}
+
+fn parse_speed(arg: &str) -> Result<f64> {
+    let speed: f64 = arg.parse()?;
+    if !(speed > 0.0 && speed.is_finite()) {
+        return Err(anyhow!("speed must be a positive number"));
+    }
+    Ok(speed)
+}
+
+/// Watchlist from the command line, or the configured one when empty
+pub fn watchlist(instruments: Vec<String>, config: &Config) -> Vec<String> {
+    if instruments.is_empty() {
+        config.watchlist.active()
+    } else {
+        instruments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::binance;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(["crypto_tui"].iter().chain(args))
+    }
+
+    #[test]
+    fn defaults_to_the_dashboard() {
+        let Command::Tui {
+            instruments,
+            record,
+        } = parse(&["BTCUSD-PERP", "ETH_USDT"]).unwrap().into_command()
+        else {
+            panic!("expected the dashboard");
+        };
+        assert_eq!(instruments, ["BTCUSD-PERP", "ETH_USDT"]);
+        assert_eq!(record, None);
+
+        let command = parse(&["stream", "BTCUSDT"]).unwrap().into_command();
+        assert!(matches!(command, Command::Stream { instruments } if instruments == ["BTCUSDT"]));
+        assert!(parse(&["btc-perp"]).is_err());
+        assert!(parse(&["stream", "BTC USD"]).is_err());
+    }
+
+    #[test]
+    fn takes_the_watchlist_from_the_config_unless_given() {
+        let config = Config::default();
+        assert_eq!(watchlist(Vec::new(), &config), config.watchlist.active());
+        let given = vec!["SOLUSD-PERP".to_string()];
+        assert_eq!(watchlist(given.clone(), &config), given);
+    }
+
+    #[test]
+    fn checks_the_replay_speed() {
+        let speed = |args: &[&str]| -> Result<(f64, bool), clap::Error> {
+            match parse(args)?.into_command() {
+                Command::Replay { speed, step, .. } => Ok((speed, step)),
+                command => panic!("expected a replay, got {:?}", command),
+            }
+        };
+        assert_eq!(speed(&["replay", "a.gz"]).unwrap(), (1.0, false));
+        assert_eq!(
+            speed(&["replay", "a.gz", "--speed", "20"]).unwrap(),
+            (20.0, false)
+        );
+        assert_eq!(speed(&["replay", "a.gz", "--step"]).unwrap(), (1.0, true));
+        for bad in ["0", "-2", "inf", "NaN", "fast"] {
+            assert!(
+                speed(&["replay", "a.gz", "--speed", bad]).is_err(),
+                "{}",
+                bad
+            );
+        }
+        let error = speed(&["replay", "a.gz", "--step", "--speed", "2"]).unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn overrides_the_config() {
+        let cli = parse(&[
+            "stream",
+            "--venue",
+            "binance",
+            "--url",
+            "wss://fstream.binance.com/stream",
+            "--compare",
+            "crypto.com",
+            "--capacity",
+            "50",
+            "--theme",
+            "mono",
+        ])
+        .unwrap();
+        let mut config = Config::default();
+        cli.global.apply(&mut config).unwrap();
+        assert_eq!(config.endpoint.venue, binance::VENUE);
+        assert_eq!(
+            config.endpoint.binance_url,
+            "wss://fstream.binance.com/stream"
+        );
+        assert_eq!(config.endpoint.ws_url, Config::default().endpoint.ws_url);
+        assert_eq!(config.endpoint.compare, ["crypto.com"]);
+        assert_eq!((config.buffers.tickers, config.buffers.trades), (50, 50));
+
+        for args in [
+            &["--capacity", "0"][..],
+            &["--venue", "kraken"],
+            &["--url", "http://localhost"],
+            &["--theme", "neon"],
+        ] {
+            let cli = parse(args).unwrap();
+            assert!(
+                cli.global.apply(&mut Config::default()).is_err(),
+                "{:?}",
+                args
+            );
+        }
+        assert!(parse(&["--log-level", "loud"]).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::alerts::Alerts;
+use crate::config::Theme;
+use crate::delivery::Delivery;
+use chrono::{DateTime, Local};
+use ratatui::{
+    buffer::Buffer,
+    layout::Layout,
+    prelude::{Constraint, Rect},
+    style::Stylize,
+    widgets::{Block, Cell, Row, Table, Widget},
+};
+use std::collections::VecDeque;
+
+fn time(millis: i64) -> String {
+    DateTime::from_timestamp_millis(millis)
+        .map(|t| t.with_timezone(&Local).format("%H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// Alert rules with their state above the alerts fired this session and,
+/// with hooks configured, how passing them on went
+pub struct AlertLog<'a> {
+    alerts: &'a Alerts,
+    deliveries: &'a VecDeque<Delivery>,
+    theme: &'a Theme,
+}
+
+impl<'a> AlertLog<'a> {
+    pub fn new(alerts: &'a Alerts, deliveries: &'a VecDeque<Delivery>, theme: &'a Theme) -> Self {
+        Self {
+            alerts,
+            deliveries,
+            theme,
+        }
+    }
+}
+
+impl<'a> Widget for AlertLog<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::bordered().title(format!("Alerts ({} fired)", self.alerts.log.len()));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let rules_height = (self.alerts.alerts.len() as u16 + 1).min(inner.height / 2);
+        let deliveries_height = if self.deliveries.is_empty() {
+            0
+        } else {
+            inner.height / 3
+        };
+        let [rules_area, log_area, deliveries_area] = Layout::vertical([
+            Constraint::Length(rules_height),
+            Constraint::Min(0),
+            Constraint::Length(deliveries_height),
+        ])
+        .areas(inner);
+
+        let headers = Row::new(["#", "Rule", "State", "Fired"])
+            .bg(self.theme.header_bg)
+            .fg(self.theme.header_fg);
+        let rows = self.alerts.alerts.iter().enumerate().map(|(i, alert)| {
+            let state = match (alert.armed, alert.fired) {
+                (false, _) => Cell::new("off").dim(),
+                (true, true) => Cell::new("FIRED").fg(self.theme.warn),
+                (true, false) => Cell::new("armed").fg(self.theme.up),
+            };
+            Row::new([
+                Cell::new(format!("{}", i + 1)),
+                Cell::new(alert.rule.to_string()),
+                state,
+                Cell::new(alert.fired_at.map(time).unwrap_or_default()),
+            ])
+        });
+        Table::new(
+            rows,
+            [
+                Constraint::Length(3),
+                Constraint::Fill(1),
+                Constraint::Length(6),
+                Constraint::Length(8),
+            ],
+        )
+        .header(headers)
+        .render(rules_area, buf);
+
+        let rows = self.alerts.log.iter().map(|fired| {
+            Row::new([
+                Cell::new(time(fired.time)),
+                Cell::new(fired.rule.as_str()),
+                Cell::new(fired.value.as_str()).fg(self.theme.warn),
+            ])
+        });
+        Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Fill(1),
+                Constraint::Length(14),
+            ],
+        )
+        .header(Row::new(["Time", "Fired", "Value"]).dim())
+        .render(log_area, buf);
+
+        let rows = self.deliveries.iter().map(|delivery| {
+            let outcome = match &delivery.error {
+                None => Cell::new("sent").fg(self.theme.up),
+                Some(error) => Cell::new(error.as_str()).fg(self.theme.down),
+            };
+            Row::new([
+                Cell::new(time(delivery.time)),
+                Cell::new(delivery.hook),
+                Cell::new(delivery.rule.as_str()),
+                Cell::new(format!("{}", delivery.attempts)),
+                outcome,
+            ])
+        });
+        Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Length(7),
+                Constraint::Fill(1),
+                Constraint::Length(5),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(Row::new(["Time", "Hook", "Alert", "Tries", "Outcome"]).dim())
+        .render(deliveries_area, buf);
+    }
+}
@@ -1,133 +1,360 @@
-use crate::models::DataList;
+use crate::config::{IndicatorConfig, Theme};
+use crate::indicators::{Indicators, Point, Study};
+use crate::models::{Candle, CandleSeries, Interval};
 use chrono::{DateTime, Local};
 use ratatui::{
     buffer::Buffer,
+    layout::{Constraint, Layout},
     prelude::Rect,
-    style::{Color, Modifier, Style, Stylize},
-    symbols,
-    widgets::{Axis, Block, Chart, Dataset, Widget},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Widget},
 };
-use std::collections::HashMap;
 
-pub struct TkrChart {
-    upticks: Vec<(f64, f64)>,
-    downticks: Vec<(f64, f64)>,
+// Each candle takes a column plus a gap column
+const CANDLE_WIDTH: u16 = 2;
+const LINE: &str = "·";
+
+pub struct TkrChart<'a> {
+    series: &'a CandleSeries,
+    indicators: Option<&'a Indicators>,
+    studies: &'a [Study],
+    params: &'a IndicatorConfig,
+    theme: &'a Theme,
 }
 
-impl TkrChart {
-    pub fn new(tkr_data: &DataList) -> Self {
-        let mut data: Vec<(i64, f64)> = Vec::new();
-        for i in tkr_data.get_order() {
-            data.push((
-                (tkr_data.data[i].t as f64 / 5_000.0).round() as i64,
-                tkr_data.data[i].a.parse::<f64>().unwrap_or(0.0),
-            ));
-        }
-
-        // create candlestick data
-        let mut candle_data: HashMap<i64, [f64; 2]> = HashMap::new();
-        for (x, y) in data {
-            if let Some([y_open, _]) = candle_data.get(&x) {
-                candle_data.insert(x, [*y_open, y]);
-            } else {
-                candle_data.insert(x, [y, y]);
-            }
+impl<'a> TkrChart<'a> {
+    pub fn new(
+        series: &'a CandleSeries,
+        indicators: Option<&'a Indicators>,
+        studies: &'a [Study],
+        params: &'a IndicatorConfig,
+        theme: &'a Theme,
+    ) -> Self {
+        Self {
+            series,
+            indicators,
+            studies,
+            params,
+            theme,
         }
+    }
 
-        let mut upticks: Vec<(f64, f64)> = Vec::new();
-        let mut downticks: Vec<(f64, f64)> = Vec::new();
-        for (key, value) in candle_data {
-            if value[1] > value[0] {
-                downticks.push((key as f64, value[1]));
-            } else {
-                upticks.push((key as f64, value[0]));
+    fn shows(&self, study: Study) -> bool {
+        self.studies.contains(&study)
+    }
+
+    fn color(&self, study: Study) -> Color {
+        match study {
+            Study::Sma => self.theme.sma,
+            Study::Ema | Study::Macd => self.theme.ema,
+            Study::Vwap => self.theme.vwap,
+            Study::Bollinger => self.theme.bands,
+            Study::Rsi => self.theme.warn,
+        }
+    }
+
+    fn label(&self, study: Study) -> String {
+        let p = self.params;
+        match study {
+            Study::Sma => format!("SMA{}", p.sma),
+            Study::Ema => format!("EMA{}", p.ema),
+            Study::Vwap => "VWAP".to_string(),
+            Study::Bollinger => format!("BB{},{}", p.bollinger, p.bollinger_k),
+            Study::Rsi => format!("RSI{}", p.rsi),
+            Study::Macd => format!("MACD{},{},{}", p.macd[0], p.macd[1], p.macd[2]),
+        }
+    }
+
+    /// Overlay values of `point` that are shown, with their colours
+    fn overlays(&self, point: &Point) -> Vec<(f64, Color)> {
+        let mut values = Vec::new();
+        if let (true, Some(bands)) = (self.shows(Study::Bollinger), point.bands) {
+            let color = self.color(Study::Bollinger);
+            values.extend([bands.upper, bands.middle, bands.lower].map(|v| (v, color)));
+        }
+        for (study, value) in [
+            (Study::Vwap, point.vwap),
+            (Study::Ema, point.ema),
+            (Study::Sma, point.sma),
+        ] {
+            if let (true, Some(value)) = (self.shows(study), value) {
+                values.push((value, self.color(study)));
             }
         }
+        values
+    }
+}
 
-        Self { upticks, downticks }
+/// Row of `value` in `area` scaled between `low` and `high`
+fn row(value: f64, high: f64, low: f64, area: Rect) -> u16 {
+    if high <= low {
+        return area.y + area.height / 2;
     }
+    let frac = ((high - value) / (high - low)).clamp(0.0, 1.0);
+    area.y + (frac * (area.height - 1) as f64).round() as u16
+}
+
+/// Height of each oscillator pane below the candles
+fn pane_height(height: u16) -> u16 {
+    (height / 5).max(3)
 }
 
-impl Widget for TkrChart {
+impl<'a> Widget for TkrChart<'a> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let datasets = vec![
-            Dataset::default()
-                .marker(symbols::Marker::Block)
-                .style(Style::default().fg(Color::Green))
-                .data(&self.upticks),
-            Dataset::default()
-                .marker(symbols::Marker::Block)
-                .style(Style::default().fg(Color::Red))
-                .data(&self.downticks),
-        ];
+        let interval = self.series.interval;
+        let mut title = vec![Span::from(format!("Chart {}", interval.label()))];
+        for study in self.studies {
+            title.push(Span::from(" "));
+            title.push(Span::from(self.label(*study)).fg(self.color(*study)));
+        }
+        let block = Block::bordered().title(Line::from(title));
+        let inner = block.inner(area);
+        block.render(area, buf);
 
-        let max_x_up = self
-            .upticks
-            .iter()
-            .map(|(x, _)| *x)
-            .fold(f64::NEG_INFINITY, f64::max);
-        let max_x_down = self
-            .downticks
+        if self.series.candles.is_empty() {
+            Paragraph::new("Waiting for trades...").render(inner, buf);
+            return;
+        }
+
+        let axis_style = Style::default().fg(self.theme.axis);
+
+        let show_rsi = self.indicators.is_some() && self.shows(Study::Rsi);
+        let show_macd = self.indicators.is_some() && self.shows(Study::Macd);
+        let pane =
+            |shown: bool| Constraint::Length(if shown { pane_height(inner.height) } else { 0 });
+        let [price_area, rsi_area, macd_area, volume_area, time_area] = Layout::vertical([
+            Constraint::Min(0),
+            pane(show_rsi),
+            pane(show_macd),
+            Constraint::Length((inner.height / 5).max(3)),
+            Constraint::Length(1),
+        ])
+        .areas(inner);
+
+        // Price labels sit in a left gutter sized to the widest price held
+        let widest = self
+            .series
+            .candles
             .iter()
-            .map(|(x, _)| *x)
-            .fold(f64::NEG_INFINITY, f64::max);
-        let max_x = if max_x_up > max_x_down {
-            max_x_up
-        } else {
-            max_x_down
-        };
-        let min_x = max_x - 50.0;
+            .map(|c| format!("{:.2}", c.high).len())
+            .max()
+            .unwrap_or(0)
+            .max(5); // room for the oscillator labels
+        let gutter = widest as u16 + 1;
+        let [price_labels, plot] =
+            Layout::horizontal([Constraint::Length(gutter), Constraint::Min(0)]).areas(price_area);
+        let [volume_labels, volume_plot] =
+            Layout::horizontal([Constraint::Length(gutter), Constraint::Min(0)]).areas(volume_area);
+        let [_, time_plot] =
+            Layout::horizontal([Constraint::Length(gutter), Constraint::Min(0)]).areas(time_area);
+        let [rsi_labels, rsi_plot] =
+            Layout::horizontal([Constraint::Length(gutter), Constraint::Min(0)]).areas(rsi_area);
+        let [macd_labels, macd_plot] =
+            Layout::horizontal([Constraint::Length(gutter), Constraint::Min(0)]).areas(macd_area);
 
-        let min_y_up = self
-            .upticks
+        if plot.height == 0 || plot.width == 0 {
+            return;
+        }
+
+        // Only the candles that fit, newest on the right
+        let visible = (plot.width / CANDLE_WIDTH) as usize;
+        let skip = self.series.candles.len().saturating_sub(visible);
+        let candles: Vec<&Candle> = self.series.candles.iter().skip(skip).collect();
+        let points: Vec<Option<&Point>> = candles
             .iter()
-            .filter(|(_, y)| *y != 0.0)
-            .map(|(_, y)| *y)
-            .fold(f64::INFINITY, f64::min);
-        let min_y_down = self
-            .downticks
+            .map(|c| {
+                self.indicators
+                    .and_then(|indicators| indicators.at(c.start))
+            })
+            .collect();
+        let overlays: Vec<Vec<(f64, Color)>> = points
             .iter()
-            .filter(|(_, y)| *y != 0.0)
-            .map(|(_, y)| *y)
-            .fold(f64::INFINITY, f64::min);
-        let mut min_y = if min_y_up > min_y_down {
-            min_y_down
-        } else {
-            min_y_up
-        };
-        min_y = (min_y / 10.0).floor() * 10.0;
+            .map(|point| point.map(|p| self.overlays(p)).unwrap_or_default())
+            .collect();
 
-        let mut max_y_up = self
-            .upticks
+        // The scale takes in the overlays too, so bands aren't cut off
+        let overlay_values = || overlays.iter().flatten().map(|(v, _)| *v);
+        let high = candles
             .iter()
-            .map(|(_, y)| *y)
+            .map(|c| c.high)
+            .chain(overlay_values())
             .fold(f64::NEG_INFINITY, f64::max);
-        let mut max_y_down = self
-            .downticks
+        let low = candles
             .iter()
-            .map(|(_, y)| *y)
-            .fold(f64::NEG_INFINITY, f64::max);
-        let mut max_y = if max_y_up > max_y_down {
-            max_y_up
-        } else {
-            max_y_down
+            .map(|c| c.low)
+            .chain(overlay_values())
+            .fold(f64::INFINITY, f64::min);
+        let max_volume = candles.iter().map(|c| c.volume).fold(0.0, f64::max);
+        let labels = [
+            format!("{:.2}", high),
+            format!("{:.2}", (high + low) / 2.0),
+            format!("{:.2}", low),
+        ];
+
+        let mid_y = price_labels.y + price_labels.height / 2;
+        let bottom_y = price_labels.y + price_labels.height - 1;
+        let label_max = (gutter - 1) as usize;
+        for (y, label) in [price_labels.y, mid_y, bottom_y].iter().zip(labels.iter()) {
+            buf.set_stringn(price_labels.x, *y, label, label_max, axis_style);
+        }
+        if volume_labels.height > 0 {
+            let label = format!("{:.2}", max_volume);
+            buf.set_stringn(
+                volume_labels.x,
+                volume_labels.y,
+                label,
+                label_max,
+                axis_style,
+            );
+        }
+
+        let row = |price: f64| row(price, high, low, plot);
+
+        // Overlays go down first and candles over them, so a line passing
+        // through a candle only shows in the gap columns either side
+        for (i, values) in overlays.iter().enumerate() {
+            let x = plot.x + i as u16 * CANDLE_WIDTH;
+            for (value, color) in values {
+                let style = Style::default().fg(*color);
+                buf.set_string(x, row(*value), LINE, style);
+                if x + 1 < plot.x + plot.width {
+                    buf.set_string(x + 1, row(*value), LINE, style);
+                }
+            }
+        }
+
+        for (i, candle) in candles.iter().enumerate() {
+            let x = plot.x + i as u16 * CANDLE_WIDTH;
+            let color = if candle.close >= candle.open {
+                self.theme.up
+            } else {
+                self.theme.down
+            };
+            let style = Style::default().fg(color);
+
+            // Wick
+            for y in row(candle.high)..=row(candle.low) {
+                buf.set_string(x, y, "│", style);
+            }
+            // Body
+            let body_top = row(candle.open.max(candle.close));
+            let body_bottom = row(candle.open.min(candle.close));
+            if candle.open == candle.close {
+                buf.set_string(x, body_top, "━", style);
+            } else {
+                for y in body_top..=body_bottom {
+                    buf.set_string(x, y, "█", style);
+                }
+            }
+
+            // Volume bar
+            if max_volume > 0.0 && volume_plot.height > 0 {
+                let bar = ((candle.volume / max_volume) * volume_plot.height as f64).ceil() as u16;
+                for dy in 0..bar.min(volume_plot.height) {
+                    let y = volume_plot.y + volume_plot.height - 1 - dy;
+                    buf.set_string(x, y, "█", style);
+                }
+            }
+        }
+
+        if show_rsi && rsi_plot.height > 0 {
+            self.render_rsi(&points, rsi_labels, rsi_plot, buf);
+        }
+        if show_macd && macd_plot.height > 0 {
+            self.render_macd(&points, macd_labels, macd_plot, buf);
+        }
+
+        // Time labels under the candles they belong to
+        let (format, label_width) = match interval {
+            Interval::S1 | Interval::S5 => ("%H:%M:%S", 8),
+            _ => ("%H:%M", 5),
         };
-        max_y = (max_y / 10.0).ceil() * 10.0;
-
-        Chart::new(datasets)
-            .block(Block::bordered())
-            .x_axis(
-                Axis::default()
-                    .title(format!("min: {}; max: {}", min_x, max_x))
-                    .style(Style::default().fg(Color::Gray))
-                    .bounds([min_x, max_x]),
-            )
-            .y_axis(
-                Axis::default()
-                    .title(format!("min: {}; max: {}", min_y, max_y))
-                    .style(Style::default().fg(Color::Gray))
-                    .bounds([min_y, max_y]),
-            )
-            .render(area, buf);
+        let step = (label_width + 2) / CANDLE_WIDTH as usize + 1;
+        for (i, candle) in candles.iter().enumerate().step_by(step) {
+            let x = time_plot.x + i as u16 * CANDLE_WIDTH;
+            if x + label_width as u16 > time_plot.x + time_plot.width {
+                break;
+            }
+            if let Some(time) = DateTime::from_timestamp_millis(candle.start) {
+                let label = time.with_timezone(&Local).format(format).to_string();
+                buf.set_string(x, time_plot.y, label, axis_style);
+            }
+        }
+    }
+}
+
+impl<'a> TkrChart<'a> {
+    /// RSI on a fixed 0-100 scale with the 70 and 30 levels marked
+    fn render_rsi(&self, points: &[Option<&Point>], labels: Rect, plot: Rect, buf: &mut Buffer) {
+        let axis_style = Style::default().fg(self.theme.axis);
+        let style = Style::default().fg(self.color(Study::Rsi));
+        let label_max = labels.width.saturating_sub(1) as usize;
+        for level in [70.0, 30.0] {
+            let y = row(level, 100.0, 0.0, plot);
+            buf.set_string(plot.x, y, "┈".repeat(plot.width as usize), axis_style);
+            buf.set_stringn(labels.x, y, format!("{}", level), label_max, axis_style);
+        }
+        let latest = points.iter().rev().find_map(|p| p.and_then(|p| p.rsi));
+        let title = latest.map_or("RSI".to_string(), |rsi| format!("RSI {:.0}", rsi));
+        buf.set_stringn(labels.x, labels.y, title, label_max, style);
+
+        for (i, point) in points.iter().enumerate() {
+            let Some(rsi) = point.and_then(|p| p.rsi) else {
+                continue;
+            };
+            let x = plot.x + i as u16 * CANDLE_WIDTH;
+            let y = row(rsi, 100.0, 0.0, plot);
+            buf.set_string(x, y, LINE, style);
+            if x + 1 < plot.x + plot.width {
+                buf.set_string(x + 1, y, LINE, style);
+            }
+        }
+    }
+
+    /// MACD and signal lines over a histogram of their difference, on a
+    /// scale centred on zero
+    fn render_macd(&self, points: &[Option<&Point>], labels: Rect, plot: Rect, buf: &mut Buffer) {
+        let axis_style = Style::default().fg(self.theme.axis);
+        let macd_style = Style::default().fg(self.color(Study::Macd));
+        let signal_style = Style::default().fg(self.theme.sma);
+        let label_max = labels.width.saturating_sub(1) as usize;
+
+        let values: Vec<_> = points.iter().map(|p| p.and_then(|p| p.macd)).collect();
+        let extent = values
+            .iter()
+            .flatten()
+            .flat_map(|v| [Some(v.macd), v.signal, v.histogram()])
+            .flatten()
+            .fold(0.0, |max: f64, v| max.max(v.abs()));
+        let row = |value: f64| row(value, extent, -extent, plot);
+        let zero = row(0.0);
+        buf.set_string(plot.x, zero, "┈".repeat(plot.width as usize), axis_style);
+        buf.set_stringn(labels.x, labels.y, "MACD", label_max, macd_style);
+        buf.set_stringn(labels.x, zero, "0", label_max, axis_style);
+
+        for (i, value) in values.iter().enumerate() {
+            let Some(value) = value else {
+                continue;
+            };
+            let x = plot.x + i as u16 * CANDLE_WIDTH;
+            if let Some(histogram) = value.histogram() {
+                let color = if histogram >= 0.0 {
+                    self.theme.up
+                } else {
+                    self.theme.down
+                };
+                let y = row(histogram);
+                for y in y.min(zero)..=y.max(zero) {
+                    buf.set_string(x, y, "█", Style::default().fg(color));
+                }
+            }
+            if x + 1 < plot.x + plot.width {
+                buf.set_string(x + 1, row(value.macd), LINE, macd_style);
+                if let Some(signal) = value.signal {
+                    buf.set_string(x + 1, row(signal), LINE, signal_style);
+                }
+            }
+        }
     }
 }
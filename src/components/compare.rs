@@ -0,0 +1,368 @@
+use crate::config::Theme;
+use crate::models::{DataList, LinkState, MarketKey, TkrData};
+use ratatui::{
+    buffer::Buffer,
+    layout::Layout,
+    prelude::{Constraint, Rect},
+    style::Stylize,
+    text::Line,
+    widgets::{Block, Cell, Row, Sparkline, Table, Widget},
+};
+use rust_decimal::prelude::ToPrimitive;
+use std::collections::HashMap;
+
+// Sparkline bars are integers, so the spread is drawn in tenths of a bp
+const SPARK_SCALE: f64 = 10.0;
+
+/// Best bid/ask of one venue
+struct Quote<'a> {
+    venue: &'a str,
+    bid: Option<f64>,
+    ask: Option<f64>,
+    mid: f64,
+}
+
+/// Mid of a ticker as f64 for the bps arithmetic
+fn mid(data: &TkrData) -> Option<f64> {
+    data.mid()?.to_f64().filter(|mid| *mid > 0.0)
+}
+
+/// One instrument lined up across venues: quotes, who is rich or cheap
+/// and the history of the spread between the highest and lowest mid
+pub struct VenueComparison<'a> {
+    tkr: &'a str,
+    venues: &'a [String],
+    data: &'a HashMap<MarketKey, DataList>,
+    links: &'a HashMap<String, LinkState>,
+    theme: &'a Theme,
+}
+
+impl<'a> VenueComparison<'a> {
+    pub fn new(
+        tkr: &'a str,
+        venues: &'a [String],
+        data: &'a HashMap<MarketKey, DataList>,
+        links: &'a HashMap<String, LinkState>,
+        theme: &'a Theme,
+    ) -> Self {
+        Self {
+            tkr,
+            venues,
+            data,
+            links,
+            theme,
+        }
+    }
+
+    fn history(&self, venue: &str) -> Option<&'a DataList> {
+        self.data.get(&MarketKey::new(venue, self.tkr))
+    }
+
+    fn quotes(&self) -> Vec<Quote<'a>> {
+        self.venues
+            .iter()
+            .filter_map(|venue| {
+                let data = self.history(venue)?.latest()?;
+                Some(Quote {
+                    venue,
+                    bid: data.bid.and_then(|bid| bid.to_f64()),
+                    ask: data.ask.and_then(|ask| ask.to_f64()),
+                    mid: mid(data)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Max minus min mid in bps of the mean, replayed from each venue's
+    /// ticker history in time order, newest last
+    fn spread_history(&self, n: usize) -> Vec<u64> {
+        let histories: Vec<(usize, &DataList)> = self
+            .venues
+            .iter()
+            .enumerate()
+            .filter_map(|(i, venue)| Some((i, self.history(venue)?)))
+            .collect();
+        // Only the span every venue with data has history for; a buffer
+        // that reaches further back would otherwise skew the early spreads
+        let start = histories
+            .iter()
+            .filter_map(|(_, history)| history.data.oldest())
+            .map(|data| data.t)
+            .max()
+            .unwrap_or(0);
+
+        let mut updates: Vec<(i64, usize, f64)> = Vec::new();
+        for (i, history) in histories {
+            updates.extend(
+                history
+                    .data
+                    .since(start)
+                    .filter_map(|data| Some((data.t, i, mid(data)?))),
+            );
+        }
+        updates.sort_by_key(|(t, _, _)| *t);
+
+        let mut mids: Vec<Option<f64>> = vec![None; self.venues.len()];
+        let mut spreads = Vec::with_capacity(updates.len());
+        for (_, i, mid) in updates {
+            mids[i] = Some(mid);
+            if let Some(spread) = spread_bps(mids.iter().flatten().copied()) {
+                spreads.push((spread * SPARK_SCALE).round() as u64);
+            }
+        }
+        let skip = spreads.len().saturating_sub(n);
+        spreads.split_off(skip)
+    }
+}
+
+fn price(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{:.2}", v))
+}
+
+/// Spread between the highest and lowest of two or more mids, in bps
+fn spread_bps(mids: impl Iterator<Item = f64>) -> Option<f64> {
+    let (mut min, mut max, mut sum, mut n) = (f64::MAX, f64::MIN, 0.0, 0);
+    for mid in mids {
+        min = min.min(mid);
+        max = max.max(mid);
+        sum += mid;
+        n += 1;
+    }
+    if n < 2 {
+        return None;
+    }
+    Some((max - min) / (sum / n as f64) * 10_000.0)
+}
+
+impl<'a> Widget for VenueComparison<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::bordered().title(format!("Compare {}", self.tkr));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        // header, one row per venue and the cross-venue line
+        let table_height = self.venues.len() as u16 + 2;
+        let [table_area, spark_area] =
+            Layout::vertical([Constraint::Length(table_height), Constraint::Min(0)]).areas(inner);
+
+        let quotes = self.quotes();
+        let mean = quotes.iter().map(|q| q.mid).sum::<f64>() / quotes.len().max(1) as f64;
+        let rich = quotes.iter().max_by(|a, b| a.mid.total_cmp(&b.mid));
+        let cheap = quotes.iter().min_by(|a, b| a.mid.total_cmp(&b.mid));
+        let spread = spread_bps(quotes.iter().map(|q| q.mid));
+
+        let headers = Row::new(["Venue", "Bid", "Ask", "Mid", "vs mean", ""])
+            .bg(self.theme.header_bg)
+            .fg(self.theme.header_fg);
+
+        let mut rows: Vec<Row> = Vec::with_capacity(self.venues.len() + 1);
+        for venue in self.venues {
+            let link = self.links.get(venue).copied().unwrap_or_default();
+            let Some(quote) = quotes.iter().find(|q| q.venue == venue) else {
+                rows.push(Row::new([Cell::new(venue.as_str()), Cell::new("-")]).dim());
+                continue;
+            };
+            let tag = match link {
+                LinkState::Reconnecting(_) | LinkState::Disconnected => "stale".fg(self.theme.warn),
+                _ if spread.is_none() => "".into(),
+                _ if rich.is_some_and(|r| r.venue == quote.venue) => "rich".fg(self.theme.down),
+                _ if cheap.is_some_and(|c| c.venue == quote.venue) => "cheap".fg(self.theme.up),
+                _ => "".into(),
+            };
+            rows.push(Row::new([
+                Cell::new(venue.as_str()),
+                Cell::new(price(quote.bid)),
+                Cell::new(price(quote.ask)),
+                Cell::new(format!("{:.2}", quote.mid)),
+                Cell::new(format!("{:+.1} bps", (quote.mid - mean) / mean * 10_000.0)),
+                Cell::new(tag),
+            ]));
+        }
+
+        // Buying the cheap venue's ask and selling the rich venue's bid;
+        // positive only when the books cross
+        let cross = match (rich, cheap, spread) {
+            (Some(rich), Some(cheap), Some(spread)) => {
+                let edge = match (rich.bid, cheap.ask) {
+                    (Some(bid), Some(ask)) => {
+                        let edge = (bid - ask) / mean * 10_000.0;
+                        let color = if edge > 0.0 {
+                            self.theme.up
+                        } else {
+                            self.theme.down
+                        };
+                        Cell::new(format!("{:+.1} bps", edge)).fg(color)
+                    }
+                    _ => Cell::new("-"),
+                };
+                Row::new([
+                    Cell::new("Spread"),
+                    Cell::new(format!("{:.1} bps", spread)),
+                    Cell::new(""),
+                    Cell::new("edge"),
+                    edge,
+                    Cell::new(format!("buy {} sell {}", cheap.venue, rich.venue)),
+                ])
+            }
+            _ => Row::new([Cell::new("Spread"), Cell::new("-")]),
+        };
+        rows.push(cross.bold());
+
+        Table::new(
+            rows,
+            [
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(headers)
+        .render(table_area, buf);
+
+        if spark_area.height < 2 {
+            return;
+        }
+        let [spark_title, spark_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(spark_area);
+        let history = self.spread_history(spark_area.width as usize);
+        let peak = history.iter().max().copied().unwrap_or(0) as f64 / SPARK_SCALE;
+        Line::from(format!("Spread history, peak {:.1} bps", peak))
+            .dim()
+            .render(spark_title, buf);
+        Sparkline::default()
+            .data(&history)
+            .style(self.theme.axis)
+            .render(spark_area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TkrResult;
+    use ratatui::{backend::TestBackend, Terminal};
+    use rust_decimal::Decimal;
+
+    const TKR: &str = "BTCUSD-PERP";
+
+    fn venues() -> Vec<String> {
+        vec!["crypto.com".to_string(), "binance".to_string()]
+    }
+
+    /// Ticker history of a venue from (time, bid, ask)
+    fn history(venue: &str, quotes: &[(i64, i64, i64)]) -> (MarketKey, DataList) {
+        let data = quotes
+            .iter()
+            .map(|&(t, bid, ask)| TkrData {
+                bid: Some(Decimal::from(bid)),
+                ask: Some(Decimal::from(ask)),
+                t,
+                ..TkrData::default()
+            })
+            .collect();
+        let mut list = DataList::new(100);
+        list.insert(&TkrResult::new(venue, TKR, "ticker", data));
+        (MarketKey::new(venue, TKR), list)
+    }
+
+    fn render(data: &HashMap<MarketKey, DataList>) -> String {
+        let (venues, links, theme) = (venues(), HashMap::new(), Theme::default());
+        let comparison = VenueComparison::new(TKR, &venues, data, &links, &theme);
+        let mut terminal = Terminal::new(TestBackend::new(100, 8)).unwrap();
+        terminal
+            .draw(|frame| frame.render_widget(comparison, frame.area()))
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        let lines: Vec<String> = buffer
+            .content
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect();
+        lines.join("\n")
+    }
+
+    fn row<'a>(screen: &'a str, label: &str) -> &'a str {
+        screen.lines().find(|line| line.contains(label)).unwrap()
+    }
+
+    #[test]
+    fn spreads_are_the_range_in_bps_of_the_mean() {
+        assert_eq!(spread_bps([100.0].into_iter()), None);
+        assert_eq!(spread_bps(std::iter::empty()), None);
+        // Unsigned: whichever venue is higher
+        let spread = spread_bps([101.0, 99.0].into_iter()).unwrap();
+        assert!((spread - 200.0).abs() < 1e-9);
+        assert_eq!(spread_bps([99.0, 101.0].into_iter()), Some(spread));
+        let three = spread_bps([100.0, 102.0, 101.0].into_iter()).unwrap();
+        assert!((three - 200.0 / 101.0 * 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lines_up_venues_ticking_at_different_times() {
+        let data = HashMap::from([
+            history("crypto.com", &[(1, 99, 101), (3, 100, 102), (5, 99, 101)]),
+            history("binance", &[(2, 100, 100), (6, 102, 102)]),
+        ]);
+        let (venues, links, theme) = (venues(), HashMap::new(), Theme::default());
+        let comparison = VenueComparison::new(TKR, &venues, &data, &links, &theme);
+        // From t=2, where both have history: 3 pairs 101 with 100, 5 pairs
+        // 100 with 100 and 6 pairs 100 with 102; t=1 predates binance
+        let expected = [
+            spread_bps([101.0, 100.0].into_iter()),
+            spread_bps([100.0, 100.0].into_iter()),
+            spread_bps([100.0, 102.0].into_iter()),
+        ]
+        .map(|bps| (bps.unwrap() * SPARK_SCALE).round() as u64);
+        assert_eq!(comparison.spread_history(10), expected);
+        assert_eq!(comparison.spread_history(2), expected[1..]);
+    }
+
+    #[test]
+    fn leaves_out_venues_without_a_price() {
+        let data = HashMap::from([
+            history("crypto.com", &[(1, 0, 0), (2, 0, 0)]),
+            history("binance", &[(1, 100, 102), (2, 100, 102)]),
+        ]);
+        let (venues, links, theme) = (venues(), HashMap::new(), Theme::default());
+        let comparison = VenueComparison::new(TKR, &venues, &data, &links, &theme);
+        assert!(comparison.spread_history(10).is_empty());
+
+        for screen in [render(&data), render(&HashMap::new())] {
+            assert!(
+                !screen.contains("NaN") && !screen.contains("inf"),
+                "{}",
+                screen
+            );
+            let spread = row(&screen, "Spread");
+            assert!(
+                spread.contains('-') && !spread.contains("bps"),
+                "{}",
+                screen
+            );
+        }
+    }
+
+    #[test]
+    fn marks_the_rich_venue_above_the_mean() {
+        let data = HashMap::from([
+            history("crypto.com", &[(1, 101, 101)]),
+            history("binance", &[(1, 99, 99)]),
+        ]);
+        let screen = render(&data);
+        let row = |label| row(&screen, label);
+        assert!(row("crypto.com").contains("+100.0 bps"), "{}", screen);
+        assert!(row("crypto.com").contains("rich"), "{}", screen);
+        assert!(row("binance").contains("-100.0 bps"), "{}", screen);
+        assert!(row("binance").contains("cheap"), "{}", screen);
+        // Selling at 101 what costs 99 to buy crosses the books
+        assert!(row("Spread").contains("+200.0 bps"), "{}", screen);
+        assert!(
+            row("Spread").contains("buy binance sell crypto.com"),
+            "{}",
+            screen
+        );
+    }
+}
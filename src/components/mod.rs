@@ -1,3 +1,11 @@
+pub mod alert_log;
 pub mod chart;
+pub mod compare;
+pub mod order_book;
+pub mod picker;
+pub mod portfolio;
+pub mod prompt;
+pub mod status_bar;
 pub mod summary;
 pub mod tkr_tab;
+pub mod trade_tape;
@@ -0,0 +1,101 @@
+use crate::config::Theme;
+use crate::models::OrderBook;
+use ratatui::{
+    buffer::Buffer,
+    prelude::{Constraint, Rect},
+    style::{Color, Stylize},
+    widgets::{Block, Cell, Row, Table, Widget},
+};
+
+pub struct OrderBookLadder<'a> {
+    book: &'a OrderBook,
+    theme: &'a Theme,
+}
+
+impl<'a> OrderBookLadder<'a> {
+    pub fn new(book: &'a OrderBook, theme: &'a Theme) -> Self {
+        Self { book, theme }
+    }
+}
+
+impl<'a> Widget for OrderBookLadder<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut title = if self.book.synced {
+            "Book".to_string()
+        } else {
+            "Book (out of sync)".to_string()
+        };
+        if self.book.malformed > 0 {
+            title.push_str(&format!(" ({} bad levels)", self.book.malformed));
+        }
+        let block = Block::bordered().title(title);
+        let headers = Row::new(["Price", "Size", "Total", "Depth"])
+            .bg(self.theme.header_bg)
+            .fg(self.theme.header_fg);
+
+        // borders, header and spread row take 4 lines; split the rest evenly
+        let levels = (area.height.saturating_sub(4) / 2) as usize;
+        let bar_width = area.width.saturating_sub(2 + 3 * 12 + 3) as usize;
+
+        let bids = cumulative(&self.book.bids, levels);
+        let asks = cumulative(&self.book.asks, levels);
+        let max_total = bids
+            .iter()
+            .chain(asks.iter())
+            .map(|(_, _, total)| *total)
+            .fold(0.0, f64::max);
+
+        let level_row = |(price, qty, total): &(f64, f64, f64), color: Color| {
+            let bar_len = if max_total > 0.0 {
+                ((total / max_total) * bar_width as f64).round() as usize
+            } else {
+                0
+            };
+            Row::new([
+                Cell::new(format!("{:.2}", price)).fg(color),
+                Cell::new(format!("{:.4}", qty)),
+                Cell::new(format!("{:.4}", total)),
+                Cell::new("█".repeat(bar_len)).fg(color),
+            ])
+        };
+
+        let mut rows: Vec<Row> = Vec::with_capacity(2 * levels + 1);
+        // Asks on top with the best ask next to the spread
+        for level in asks.iter().rev() {
+            rows.push(level_row(level, self.theme.down));
+        }
+        let spread = match self.book.spread() {
+            Some(spread) => format!("{:.2}", spread),
+            None => "-".to_string(),
+        };
+        rows.push(Row::new([Cell::new("Spread"), Cell::new(spread)]).bold());
+        for level in bids.iter() {
+            rows.push(level_row(level, self.theme.up));
+        }
+
+        Table::new(
+            rows,
+            [
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(headers)
+        .block(block)
+        .render(area, buf);
+    }
+}
+
+/// Best `n` levels of one side with a running total of size
+fn cumulative(side: &[(f64, f64)], n: usize) -> Vec<(f64, f64, f64)> {
+    let mut total = 0.0;
+    side.iter()
+        .take(n)
+        .map(|(price, qty)| {
+            total += qty;
+            (*price, *qty, total)
+        })
+        .collect()
+}
@@ -0,0 +1,75 @@
+use crate::config::Theme;
+use crate::models::Instrument;
+use ratatui::{
+    buffer::Buffer,
+    prelude::{Constraint, Rect},
+    style::{Modifier, Style, Stylize},
+    widgets::{Block, Cell, Clear, Row, Table, Widget},
+};
+
+/// Autocomplete list shown above the add prompt
+pub struct InstrumentPicker<'a> {
+    matches: &'a [&'a Instrument],
+    selected: usize,
+    theme: &'a Theme,
+}
+
+impl<'a> InstrumentPicker<'a> {
+    pub fn new(matches: &'a [&'a Instrument], selected: usize, theme: &'a Theme) -> Self {
+        Self {
+            matches,
+            selected,
+            theme,
+        }
+    }
+
+    /// Rows needed to show every match, including borders and header
+    pub fn height(&self) -> u16 {
+        self.matches.len() as u16 + 3
+    }
+}
+
+impl<'a> Widget for InstrumentPicker<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::bordered().title("Instruments (up/down) select (tab) complete");
+        let headers = Row::new(["Symbol", "Type", "Base/Quote", "Tick", "Status"])
+            .bg(self.theme.header_bg)
+            .fg(self.theme.header_fg);
+
+        let rows: Vec<Row> = self
+            .matches
+            .iter()
+            .enumerate()
+            .map(|(i, inst)| {
+                let status = if inst.tradable { "Tradable" } else { "Halted" };
+                let row = Row::new([
+                    Cell::new(inst.symbol.clone()),
+                    Cell::new(inst.inst_type.clone()),
+                    Cell::new(format!("{}/{}", inst.base_ccy, inst.quote_ccy)),
+                    Cell::new(inst.price_tick_size.clone()),
+                    Cell::new(status),
+                ]);
+                if i == self.selected {
+                    row.style(Style::default().add_modifier(Modifier::REVERSED))
+                } else {
+                    row
+                }
+            })
+            .collect();
+
+        Clear.render(area, buf);
+        Table::new(
+            rows,
+            [
+                Constraint::Length(16),
+                Constraint::Length(16),
+                Constraint::Length(12),
+                Constraint::Length(10),
+                Constraint::Length(10),
+            ],
+        )
+        .header(headers)
+        .block(block)
+        .render(area, buf);
+    }
+}
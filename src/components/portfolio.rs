@@ -0,0 +1,331 @@
+use crate::config::Theme;
+use crate::models::{DataList, Instrument, MarketKey};
+use crate::portfolio::{self, Direction, Position};
+use ratatui::{
+    buffer::Buffer,
+    layout::Layout,
+    prelude::{Constraint, Rect},
+    style::{Color, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Cell, Row, Table, Widget},
+};
+use rust_decimal::Decimal;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+const OVERFLOW: &str = "overflow";
+
+/// Net and gross notional held in one asset; None once they overflow
+struct Exposure {
+    net: Option<Decimal>,
+    gross: Option<Decimal>,
+}
+
+/// Positions marked to the last trade price of the selected venue, with
+/// unrealized P&L and exposure per asset. Quotes are taken to be USD.
+pub struct Portfolio<'a> {
+    positions: &'a [Position],
+    data: &'a HashMap<MarketKey, DataList>,
+    venue: &'a str,
+    instruments: &'a [Instrument],
+    theme: &'a Theme,
+}
+
+impl<'a> Portfolio<'a> {
+    pub fn new(
+        positions: &'a [Position],
+        data: &'a HashMap<MarketKey, DataList>,
+        venue: &'a str,
+        instruments: &'a [Instrument],
+        theme: &'a Theme,
+    ) -> Self {
+        Self {
+            positions,
+            data,
+            venue,
+            instruments,
+            theme,
+        }
+    }
+
+    fn mark(&self, tkr: &str) -> Option<Decimal> {
+        self.data
+            .get(&MarketKey::new(self.venue, tkr))?
+            .latest()?
+            .last
+    }
+
+    fn color(&self, value: Option<Decimal>) -> Color {
+        match value {
+            Some(value) if value >= Decimal::ZERO => self.theme.up,
+            Some(_) => self.theme.down,
+            None => self.theme.warn,
+        }
+    }
+}
+
+/// Adds to a total that stays None once it has overflowed
+fn add(total: &mut Option<Decimal>, value: Option<Decimal>) {
+    *total = total
+        .zip(value)
+        .and_then(|(total, value)| total.checked_add(value));
+}
+
+fn usd(value: Option<Decimal>) -> String {
+    match value {
+        Some(value) => format!("{:.2}", value.round_dp(2)),
+        None => OVERFLOW.to_string(),
+    }
+}
+
+/// P&L as a percentage of what was paid
+fn pct(pnl: Option<Decimal>, cost: Option<Decimal>) -> String {
+    let Some((pnl, cost)) = pnl.zip(cost) else {
+        return OVERFLOW.to_string();
+    };
+    if cost.is_zero() {
+        return "-".to_string();
+    }
+    match pnl
+        .checked_div(cost)
+        .and_then(|ratio| ratio.checked_mul(Decimal::ONE_HUNDRED))
+    {
+        Some(pct) => format!("{:+.2}%", pct.round_dp(2)),
+        None => OVERFLOW.to_string(),
+    }
+}
+
+impl<'a> Widget for Portfolio<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut rows: Vec<Row> = Vec::with_capacity(self.positions.len() + 1);
+        let mut exposures: Vec<(String, Exposure)> = Vec::new();
+        let zero = Some(Decimal::ZERO);
+        let (mut value, mut cost, mut pnl) = (zero, zero, zero);
+        let mut unpriced = 0;
+
+        for (i, position) in self.positions.iter().enumerate() {
+            let side = match position.side {
+                Direction::Long => Cell::new("long").fg(self.theme.up),
+                Direction::Short => Cell::new("short").fg(self.theme.down),
+            };
+            let mut cells = vec![
+                Cell::new(format!("{}", i + 1)),
+                Cell::new(position.instrument.as_str()),
+                side,
+                Cell::new(position.qty.normalize().to_string()),
+                Cell::new(position.entry.normalize().to_string()),
+            ];
+            let Some(mark) = self.mark(&position.instrument) else {
+                unpriced += 1;
+                cells.push(Cell::new("-"));
+                rows.push(Row::new(cells).dim());
+                continue;
+            };
+            let position_pnl = position.pnl(mark);
+            let notional = position.value(mark);
+            add(&mut value, notional);
+            add(&mut cost, position.cost());
+            add(&mut pnl, position_pnl);
+
+            let asset = portfolio::asset(&position.instrument, self.instruments);
+            let gross = notional.map(|notional| notional.abs());
+            match exposures.iter_mut().find(|(a, _)| *a == asset) {
+                Some((_, exposure)) => {
+                    add(&mut exposure.net, notional);
+                    add(&mut exposure.gross, gross);
+                }
+                None => exposures.push((
+                    asset,
+                    Exposure {
+                        net: notional,
+                        gross,
+                    },
+                )),
+            }
+
+            cells.extend([
+                Cell::new(mark.to_string()),
+                Cell::new(usd(notional)),
+                Cell::new(usd(position_pnl)).fg(self.color(position_pnl)),
+                Cell::new(pct(position_pnl, position.cost())).fg(self.color(position_pnl)),
+            ]);
+            rows.push(Row::new(cells));
+        }
+        rows.push(
+            Row::new([
+                Cell::new(""),
+                Cell::new("Total"),
+                Cell::new(""),
+                Cell::new(""),
+                Cell::new(usd(cost)),
+                Cell::new(""),
+                Cell::new(usd(value)),
+                Cell::new(usd(pnl)).fg(self.color(pnl)),
+                Cell::new(pct(pnl, cost)).fg(self.color(pnl)),
+            ])
+            .bold(),
+        );
+
+        let mut title = vec![Span::from("Portfolio ")];
+        if !self.positions.is_empty() {
+            let total = match pnl {
+                Some(_) => format!("{} USD", usd(pnl)),
+                None => OVERFLOW.to_string(),
+            };
+            title.push(Span::from(total).fg(self.color(pnl)));
+        }
+        if unpriced > 0 {
+            title.push(Span::from(format!(" ({} without a price)", unpriced)).fg(self.theme.warn));
+        }
+        let block = Block::bordered().title(Line::from(title));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        if self.positions.is_empty() {
+            Line::from("No positions".dim()).render(inner, buf);
+            return;
+        }
+
+        let positions_height = (rows.len() as u16 + 1).min(inner.height * 2 / 3);
+        let [positions_area, exposure_area] =
+            Layout::vertical([Constraint::Length(positions_height), Constraint::Min(0)])
+                .areas(inner);
+
+        let headers = Row::new([
+            "#",
+            "Instrument",
+            "Side",
+            "Qty",
+            "Entry",
+            "Mark",
+            "Value",
+            "P&L",
+            "P&L %",
+        ])
+        .bg(self.theme.header_bg)
+        .fg(self.theme.header_fg);
+        Table::new(
+            rows,
+            [
+                Constraint::Length(3),
+                Constraint::Length(14),
+                Constraint::Length(5),
+                Constraint::Length(10),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(9),
+            ],
+        )
+        .header(headers)
+        .render(positions_area, buf);
+
+        // Share of the gross exposure held in each asset, largest first
+        exposures.sort_by_key(|(_, exposure)| Reverse(exposure.gross));
+        let mut gross = zero;
+        for (_, exposure) in exposures.iter() {
+            add(&mut gross, exposure.gross);
+        }
+        let rows = exposures.iter().map(|(asset, exposure)| {
+            Row::new([
+                Cell::new(asset.as_str()),
+                Cell::new(usd(exposure.net)).fg(self.color(exposure.net)),
+                Cell::new(usd(exposure.gross)),
+                Cell::new(
+                    pct(exposure.gross, gross)
+                        .trim_start_matches('+')
+                        .to_string(),
+                ),
+            ])
+        });
+        Table::new(
+            rows,
+            [
+                Constraint::Length(10),
+                Constraint::Length(14),
+                Constraint::Length(14),
+                Constraint::Length(9),
+            ],
+        )
+        .header(Row::new(["Asset", "Net", "Gross", "Share"]).dim())
+        .render(exposure_area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{TkrData, TkrResult};
+    use ratatui::{backend::TestBackend, Terminal};
+
+    const MAX: &str = "79228162514264337593543950335";
+
+    fn marks(prices: &[(&str, Decimal)]) -> HashMap<MarketKey, DataList> {
+        prices
+            .iter()
+            .map(|&(tkr, last)| {
+                let data = TkrData {
+                    last: Some(last),
+                    t: 1,
+                    ..TkrData::default()
+                };
+                let mut list = DataList::new(10);
+                list.insert(&TkrResult::new("binance", tkr, "ticker", vec![data]));
+                (MarketKey::new("binance", tkr), list)
+            })
+            .collect()
+    }
+
+    fn render(positions: &[&str], data: &HashMap<MarketKey, DataList>) -> String {
+        let positions: Vec<Position> = positions
+            .iter()
+            .map(|text| Position::parse(text).unwrap())
+            .collect();
+        let theme = Theme::default();
+        let widget = Portfolio::new(&positions, data, "binance", &[], &theme);
+        let mut terminal = Terminal::new(TestBackend::new(110, 14)).unwrap();
+        terminal
+            .draw(|frame| frame.render_widget(widget, frame.area()))
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        let lines: Vec<String> = buffer
+            .content
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect();
+        lines.join("\n")
+    }
+
+    #[test]
+    fn shows_overflow_instead_of_panicking() {
+        let data = marks(&[("BTCUSDT", Decimal::TWO), ("ETHUSDT", Decimal::ONE)]);
+        let screen = render(
+            &[
+                &format!("BTCUSDT long {} @ 1", MAX),
+                &format!("ETHUSDT long {} @ 1", MAX),
+                "ETHUSDT short 1 @ 1",
+            ],
+            &data,
+        );
+        let row = |label: &str| screen.lines().find(|line| line.contains(label)).unwrap();
+        assert!(row("BTCUSDT").contains(OVERFLOW), "{}", screen);
+        assert!(row("Total").contains(OVERFLOW), "{}", screen);
+        // The ETH figures fit, even if the total of all of them doesn't
+        assert!(row("3   ETHUSDT").contains("-1.00"), "{}", screen);
+        assert!(screen.starts_with("┌Portfolio overflow─"), "{}", screen);
+    }
+
+    #[test]
+    fn totals_positions_that_fit() {
+        let data = marks(&[("BTCUSDT", Decimal::from(110))]);
+        let screen = render(&["BTCUSDT long 2 @ 100", "BTCUSDT short 1 @ 120"], &data);
+        let total = screen.lines().find(|line| line.contains("Total")).unwrap();
+        assert!(!screen.contains(OVERFLOW), "{}", screen);
+        // 220 - 110 = 110 held for 200 + 120 = 320 paid, up 20 + 10 = 30
+        assert!(total.contains("320.00"), "{}", screen);
+        assert!(total.contains("110.00"), "{}", screen);
+        assert!(total.contains("30.00"), "{}", screen);
+        assert!(total.contains("+9.38%"), "{}", screen);
+    }
+}
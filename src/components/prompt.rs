@@ -0,0 +1,114 @@
+use crate::config::Theme;
+use ratatui::{
+    buffer::Buffer,
+    prelude::Rect,
+    style::Stylize,
+    text::{Line, Span},
+    widgets::Widget,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PromptMode {
+    Add,
+    Remove,
+    Indicator,
+    Alert,
+    Position,
+}
+
+/// Single line input drawn over the status bar
+pub struct Prompt {
+    pub mode: PromptMode,
+    pub input: String,
+    pub selected: usize,       // highlighted autocomplete match
+    pub error: Option<String>, // why the last input was not accepted
+}
+
+impl Prompt {
+    pub fn new(mode: PromptMode, input: &str) -> Self {
+        Self {
+            mode,
+            input: input.to_string(),
+            selected: 0,
+            error: None,
+        }
+    }
+
+    pub fn push(&mut self, c: char) {
+        if matches!(self.mode, PromptMode::Alert | PromptMode::Position) {
+            if c.is_ascii_graphic() || c == ' ' {
+                self.input.push(c);
+            }
+        } else if self.mode == PromptMode::Indicator {
+            if c.is_ascii_alphanumeric() || c == ' ' || c == '.' {
+                self.input.push(c.to_ascii_lowercase());
+            }
+        } else if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+            self.input.push(c.to_ascii_uppercase());
+        }
+        self.selected = 0;
+        self.error = None;
+    }
+
+    pub fn pop(&mut self) {
+        self.input.pop();
+        self.selected = 0;
+        self.error = None;
+    }
+
+    pub fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn down(&mut self, matches: usize) {
+        if self.selected + 1 < matches {
+            self.selected += 1;
+        }
+    }
+
+    pub fn widget<'a>(&'a self, theme: &'a Theme) -> impl Widget + 'a {
+        PromptLine {
+            prompt: self,
+            theme,
+        }
+    }
+}
+
+struct PromptLine<'a> {
+    prompt: &'a Prompt,
+    theme: &'a Theme,
+}
+
+impl<'a> Widget for PromptLine<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let label = match self.prompt.mode {
+            PromptMode::Add => " Add instrument: ",
+            PromptMode::Remove => " Remove instrument: ",
+            PromptMode::Indicator => " Indicator: ",
+            PromptMode::Alert => " Alert: ",
+            PromptMode::Position => " Position: ",
+        };
+        let hint = match (&self.prompt.error, self.prompt.mode) {
+            (Some(error), _) => Span::from(format!("  {}", error)).fg(self.theme.down),
+            (None, PromptMode::Indicator) => {
+                Span::from("  e.g. sma 50, bb 20 2, macd 12 26 9 (esc) cancel").dim()
+            }
+            (None, PromptMode::Alert) => {
+                Span::from("  e.g. BTCUSD-PERP last > 70000, on 2, off 2, del 2 (esc) cancel").dim()
+            }
+            (None, PromptMode::Position) => {
+                Span::from("  e.g. BTCUSD-PERP long 0.5 @ 65000, del 2 (esc) cancel").dim()
+            }
+            (None, _) => Span::from("  (enter) confirm (esc) cancel").dim(),
+        };
+        Line::from(vec![
+            Span::from(label)
+                .bg(self.theme.header_bg)
+                .fg(self.theme.header_fg),
+            Span::from(format!(" {}", self.prompt.input)),
+            Span::from("█").slow_blink(),
+            hint,
+        ])
+        .render(area, buf);
+    }
+}
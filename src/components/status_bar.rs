@@ -0,0 +1,103 @@
+use crate::config::Theme;
+use crate::feed::Dropped;
+use crate::models::{ConnStatus, LinkState};
+use ratatui::{
+    buffer::Buffer,
+    prelude::Rect,
+    style::Stylize,
+    text::{Line, Span},
+    widgets::{Paragraph, Widget},
+};
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Ticker updates arrive several times a second; older than this is worth flagging
+const STALE_TKR: Duration = Duration::from_secs(5);
+
+pub struct StatusBar<'a> {
+    status: &'a ConnStatus,
+    watchlist: &'a [String],
+    refused: &'a HashMap<String, String>, // tkr -> why the venue refused it
+    dropped: Dropped,
+    theme: &'a Theme,
+    hints: &'a str,
+}
+
+impl<'a> StatusBar<'a> {
+    pub fn new(
+        status: &'a ConnStatus,
+        watchlist: &'a [String],
+        refused: &'a HashMap<String, String>,
+        dropped: Dropped,
+        theme: &'a Theme,
+        hints: &'a str,
+    ) -> Self {
+        Self {
+            status,
+            watchlist,
+            refused,
+            dropped,
+            theme,
+            hints,
+        }
+    }
+}
+
+impl<'a> Widget for StatusBar<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let theme = self.theme;
+        let state = match self.status.state {
+            LinkState::Connected => Span::from(" CONNECTED ").bg(theme.up),
+            LinkState::Reconnecting(attempt) => {
+                Span::from(format!(" RECONNECTING #{} ", attempt)).bg(theme.warn)
+            }
+            LinkState::Disconnected => Span::from(" DISCONNECTED ").bg(theme.down),
+            LinkState::Replaying => Span::from(" REPLAY ").bg(theme.warn),
+            LinkState::ReplayEnded => Span::from(" REPLAY ENDED ").bg(theme.down),
+        };
+
+        let rtt = match self.status.rtt {
+            Some(rtt) => format!("{}ms", rtt.as_millis()),
+            None => "-".to_string(),
+        };
+
+        let mut spans = vec![
+            state.fg(theme.highlight_fg),
+            Span::from(format!(
+                " rtt {} | {:.1} msg/s | reconnects {} |",
+                rtt, self.status.msg_rate, self.status.reconnects
+            )),
+        ];
+        // The Tui fell behind the feed
+        if self.dropped != Dropped::default() {
+            spans.push(
+                Span::from(format!(
+                    " dropped {} tkrs {} trades {} events |",
+                    self.dropped.tkrs, self.dropped.trades, self.dropped.events
+                ))
+                .fg(theme.warn),
+            );
+        }
+
+        for tkr in self.watchlist {
+            if let Some(reason) = self.refused.get(tkr) {
+                spans.push(Span::from(format!(" {} {}", tkr, reason)).fg(theme.down));
+                continue;
+            }
+            let age = self.status.last_msg.get(tkr).map(|t| t.elapsed());
+            let span = match age {
+                Some(age) if age < STALE_TKR => {
+                    Span::from(format!(" {} {:.1}s", tkr, age.as_secs_f64()))
+                }
+                Some(age) => {
+                    Span::from(format!(" {} {:.0}s", tkr, age.as_secs_f64())).fg(theme.down)
+                }
+                None => Span::from(format!(" {} -", tkr)).fg(theme.down),
+            };
+            spans.push(span);
+        }
+        spans.push(Span::from(format!(" | {}", self.hints)));
+
+        Paragraph::new(Line::from(spans)).render(area, buf);
+    }
+}
@@ -1,38 +1,52 @@
-use crate::models::DataList;
+use crate::config::Theme;
+use crate::models::{DataList, MarketKey};
 use ratatui::{
     buffer::Buffer,
     prelude::{Constraint, Rect},
-    style::{Color, Stylize},
+    style::Stylize,
     widgets::{Block, Cell, Row, Table, Widget},
 };
+use rust_decimal::Decimal;
 use std::collections::HashMap;
 
+/// Latest tickers of one venue
 pub struct Summary<'a> {
-    data: &'a HashMap<String, DataList>,
+    data: &'a HashMap<MarketKey, DataList>,
+    venue: &'a str,
+    theme: &'a Theme,
 }
 
 impl<'a> Summary<'a> {
-    pub fn new(data: &'a HashMap<String, DataList>) -> Self {
-        Self { data }
+    pub fn new(data: &'a HashMap<MarketKey, DataList>, venue: &'a str, theme: &'a Theme) -> Self {
+        Self { data, venue, theme }
     }
 }
 
 impl<'a> Widget for Summary<'a> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let block = Block::bordered().title("Summary");
+        let venue_data = || self.data.iter().filter(|(key, _)| key.venue == self.venue);
+        let errors: u64 = venue_data()
+            .map(|(_, data_i)| data_i.malformed + data_i.rejected)
+            .sum();
+        let title = if errors > 0 {
+            format!("Summary ({} bad updates)", errors)
+        } else {
+            "Summary".to_string()
+        };
+        let block = Block::bordered().title(title);
         let headers = Row::new(["Tkr", "Price", "Qty 24h", "Bid", "Ask"])
-            .bg(Color::Rgb(205, 214, 244))
-            .fg(Color::Rgb(17, 17, 27));
+            .bg(self.theme.header_bg)
+            .fg(self.theme.header_fg);
 
-        let mut rows: Vec<Row> = Vec::with_capacity(self.data.capacity());
-        for (key, data_i) in self.data.iter() {
-            let curr_i = data_i.curr_i;
+        let mut rows: Vec<Row> = Vec::with_capacity(self.data.len());
+        for (key, data_i) in venue_data() {
+            let latest = data_i.latest();
             rows.push(Row::new([
-                Cell::new(key.to_string()),
-                Cell::new(data_i.data[curr_i].a.clone()),
-                Cell::new(data_i.data[curr_i].v.clone()),
-                Cell::new(data_i.data[curr_i].b.clone()),
-                Cell::new(data_i.data[curr_i].k.clone()),
+                Cell::new(key.tkr.clone()),
+                Cell::new(show(latest.and_then(|data| data.last))),
+                Cell::new(show(latest.and_then(|data| data.volume))),
+                Cell::new(show(latest.and_then(|data| data.bid))),
+                Cell::new(show(latest.and_then(|data| data.ask))),
             ]));
         }
 
@@ -51,3 +65,8 @@ impl<'a> Widget for Summary<'a> {
         .render(area, buf);
     }
 }
+
+/// Exchange precision as sent, "-" when the field was null
+fn show(value: Option<Decimal>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| v.to_string())
+}
@@ -1,162 +1,264 @@
-use crate::models::DataList;
-use chrono::{DateTime, Local};
+use crate::components::order_book::OrderBookLadder;
+use crate::components::trade_tape::TradeTape;
+use crate::config::Theme;
+use crate::models::{OrderBook, TradeList};
 use ratatui::{
     buffer::Buffer,
-    prelude::{Constraint, Rect},
-    style::{Color, Modifier, Style, Stylize},
-    symbols,
-    widgets::{Block, Cell, Chart, Dataset, Row, Table, Tabs, Widget},
+    prelude::Rect,
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::Widget,
 };
 use std::collections::HashMap;
 
+const DIVIDER: &str = " | ";
+
 #[derive(Default)]
 pub struct TkrTabs {
-    pub selected_tab: SelectedTab,
+    pub selected: usize,
+    views: Vec<TabView>,
+}
+
+/// What the lower pane of a tab shows
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum TabView {
+    #[default]
+    Trades,
+    Book,
+    Compare,
+    Alerts,
+    Portfolio,
 }
 
 impl TkrTabs {
-    pub fn select(&mut self, i: usize) {
-        match i {
-            1 => self.selected_tab = SelectedTab::Tab1,
-            2 => self.selected_tab = SelectedTab::Tab2,
-            3 => self.selected_tab = SelectedTab::Tab3,
-            4 => self.selected_tab = SelectedTab::Tab4,
-            5 => self.selected_tab = SelectedTab::Tab5,
-            _ => {}
+    pub fn view(&self) -> TabView {
+        self.views.get(self.selected).copied().unwrap_or_default()
+    }
+
+    pub fn set_view(&mut self, view: TabView) {
+        if self.views.len() <= self.selected {
+            self.views.resize(self.selected + 1, TabView::default());
+        }
+        self.views[self.selected] = view;
+    }
+
+    /// Selects tab `i` (0 based) if the watchlist has it
+    pub fn select(&mut self, i: usize, len: usize) {
+        if i < len {
+            self.selected = i;
         }
     }
 
-    pub fn widget(&self, watchlist: &Vec<&str>) -> impl Widget {
-        let tab_names: Vec<String> = watchlist
+    pub fn next(&mut self, len: usize) {
+        if len > 0 {
+            self.selected = (self.selected + 1) % len;
+        }
+    }
+
+    pub fn prev(&mut self, len: usize) {
+        if len > 0 {
+            self.selected = (self.selected + len - 1) % len;
+        }
+    }
+
+    /// Drops tab `i`'s view and keeps the selection on a valid tab
+    pub fn remove(&mut self, i: usize, len: usize) {
+        if i < self.views.len() {
+            self.views.remove(i);
+        }
+        if self.selected > i || self.selected >= len {
+            self.selected = self.selected.saturating_sub(1);
+        }
+    }
+
+    pub fn selected_tkr<'a>(&self, watchlist: &'a [String]) -> Option<&'a str> {
+        watchlist.get(self.selected).map(|tkr| tkr.as_str())
+    }
+
+    pub fn widget<'a>(&self, watchlist: &'a [String]) -> TabBar<'a> {
+        TabBar {
+            watchlist,
+            selected: self.selected,
+        }
+    }
+
+    pub fn widget_trades<'a>(
+        &self,
+        trades: &'a HashMap<String, TradeList>,
+        watchlist: &[String],
+        min_qty: f64,
+        theme: &'a Theme,
+    ) -> Option<TradeTape<'a>> {
+        let tkr = self.selected_tkr(watchlist)?;
+        Some(TradeTape::new(trades.get(tkr)?, min_qty, theme))
+    }
+
+    pub fn widget_book<'a>(
+        &self,
+        books: &'a HashMap<String, OrderBook>,
+        watchlist: &[String],
+        theme: &'a Theme,
+    ) -> Option<OrderBookLadder<'a>> {
+        let tkr = self.selected_tkr(watchlist)?;
+        Some(OrderBookLadder::new(books.get(tkr)?, theme))
+    }
+}
+
+/// Tab titles scrolled so the selected tab is always in view
+pub struct TabBar<'a> {
+    watchlist: &'a [String],
+    selected: usize,
+}
+
+impl<'a> Widget for TabBar<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let titles: Vec<String> = self
+            .watchlist
             .iter()
             .enumerate()
             .map(|(i, name)| format!("({}) {}", i + 1, name))
             .collect();
-        Tabs::new(tab_names).select(self.selected_tab as usize)
-    }
-}
+        if titles.is_empty() {
+            return;
+        }
+        let selected = self.selected.min(titles.len() - 1);
 
-#[derive(Default, Clone, Copy)]
-pub enum SelectedTab {
-    #[default]
-    Tab1,
-    Tab2,
-    Tab3,
-    Tab4,
-    Tab5,
-}
+        // Leave room for the scroll markers
+        let width = (area.width as usize).saturating_sub(4);
+        let span_width = |range: &[String]| -> usize {
+            range.iter().map(|t| t.len()).sum::<usize>()
+                + DIVIDER.len() * range.len().saturating_sub(1)
+        };
 
-impl SelectedTab {
-    pub fn widget_trades(
-        &self,
-        // area: Rect,
-        // buf: &mut Buffer,
-        data: &HashMap<String, DataList>,
-        watchlist: &Vec<&str>,
-    ) -> impl Widget {
-        match self {
-            SelectedTab::Tab1 => self.render_tab(&data[watchlist[0]]),
-            SelectedTab::Tab2 => self.render_tab(&data[watchlist[1]]),
-            SelectedTab::Tab3 => self.render_tab(&data[watchlist[2]]),
-            SelectedTab::Tab4 => self.render_tab(&data[watchlist[3]]),
-            SelectedTab::Tab5 => self.render_tab(&data[watchlist[4]]),
+        // Scroll only as far as needed to bring the selected tab into view
+        let mut start = 0;
+        while start < selected && span_width(&titles[start..=selected]) > width {
+            start += 1;
+        }
+        let mut end = selected + 1;
+        while end < titles.len() && span_width(&titles[start..=end]) <= width {
+            end += 1;
         }
-    }
 
-    fn render_tab(self, data: &DataList) -> impl Widget {
-        let block = Block::bordered().title("Trades");
-        let headers = Row::new(["Time", "Price", "Qty 24h", "Bid", "Ask"])
-            .bg(Color::Rgb(205, 214, 244))
-            .fg(Color::Rgb(17, 17, 27));
-
-        // Color scheme
-        let green_color = Color::Rgb(166, 227, 161);
-        let red_color = Color::Rgb(243, 139, 168);
-        let fg_color = Color::Rgb(24, 24, 27);
-
-        let capacity = data.capacity;
-        let mut rows: Vec<Row> = Vec::with_capacity(capacity);
-
-        // Construct rows
-        for i in data.get_order() {
-            // Reset color
-            let mut row_bg_color = Color::Reset;
-            let mut row_fg_color = Color::Reset;
-            let mut bid_fg_color = Color::Reset;
-            let mut ask_fg_color = Color::Reset;
-
-            let i_prior = (capacity - 1) - ((capacity - i) % capacity);
-            let row_i = &data.data[i];
-            let row_prior = &data.data[i_prior];
-
-            let qty_i = row_i.v.parse::<f64>().unwrap_or(0.0);
-            let qty_prior = row_prior.v.parse::<f64>().unwrap_or(0.0);
-            let p_i = row_i.a.parse::<f64>().unwrap_or(0.0);
-            let p_prior = row_prior.a.parse::<f64>().unwrap_or(0.0);
-            let bid_i = row_i.b.parse::<f64>().unwrap_or(0.0);
-            let bid_prior = row_prior.b.parse::<f64>().unwrap_or(0.0);
-            let ask_i = row_i.k.parse::<f64>().unwrap_or(0.0);
-            let ask_prior = row_prior.k.parse::<f64>().unwrap_or(0.0);
-
-            // Bid/ask color
-            if bid_i > bid_prior {
-                bid_fg_color = green_color;
+        let mut spans = vec![Span::from(if start > 0 { "< " } else { "  " })];
+        for (i, title) in titles.iter().enumerate().take(end).skip(start) {
+            if i > start {
+                spans.push(Span::from(DIVIDER));
             }
-            if bid_i < bid_prior {
-                bid_fg_color = red_color;
-            }
-            if ask_i > ask_prior {
-                ask_fg_color = green_color;
-            }
-            if ask_i < ask_prior {
-                ask_fg_color = red_color;
+            let span = Span::from(title.as_str());
+            if i == selected {
+                spans.push(span.style(Style::default().add_modifier(Modifier::REVERSED)));
+            } else {
+                spans.push(span.dim());
             }
+        }
+        if end < titles.len() {
+            spans.push(Span::from(" >"));
+        }
+
+        Line::from(spans).render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{backend::TestBackend, Terminal};
 
-            // Row color
-            if qty_i > qty_prior {
-                if p_i > p_prior {
-                    row_bg_color = green_color;
-                    row_fg_color = fg_color;
-                    bid_fg_color = fg_color;
-                    ask_fg_color = fg_color;
-                } else {
-                    row_bg_color = red_color;
-                    row_fg_color = fg_color;
-                    bid_fg_color = fg_color;
-                    ask_fg_color = fg_color;
+    fn watchlist(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("TKR{}-PERP", i)).collect()
+    }
+
+    fn render(tabs: &TkrTabs, watchlist: &[String], width: u16) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(width, 1)).unwrap();
+        terminal
+            .draw(|frame| frame.render_widget(tabs.widget(watchlist), frame.area()))
+            .unwrap();
+        terminal
+            .backend()
+            .buffer()
+            .content
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect()
+    }
+
+    #[test]
+    fn moves_within_any_number_of_tabs() {
+        let mut tabs = TkrTabs::default();
+        tabs.next(0);
+        tabs.prev(0);
+        tabs.select(0, 0);
+        tabs.remove(0, 0);
+        assert_eq!(tabs.selected, 0);
+        assert_eq!(tabs.selected_tkr(&[]), None);
+
+        tabs.next(1);
+        tabs.prev(1);
+        assert_eq!(tabs.selected, 0);
+
+        tabs.prev(4);
+        assert_eq!(tabs.selected, 3);
+        tabs.next(4);
+        assert_eq!(tabs.selected, 0);
+        tabs.select(2, 4);
+        tabs.select(9, 4);
+        assert_eq!(tabs.selected, 2);
+    }
+
+    #[test]
+    fn keeps_the_selection_valid_on_remove() {
+        let list = watchlist(3);
+        let mut tabs = TkrTabs::default();
+        tabs.select(1, 3);
+        tabs.set_view(TabView::Book);
+
+        // Removing a tab before the selected one keeps the same instrument
+        tabs.select(2, 3);
+        tabs.remove(0, 2);
+        assert_eq!(tabs.selected, 1);
+        assert_eq!(tabs.selected_tkr(&list[1..]), Some("TKR2-PERP"));
+
+        // Removing the selected last tab selects the one before
+        tabs.remove(1, 1);
+        assert_eq!(tabs.selected, 0);
+        assert_eq!(tabs.view(), TabView::Book);
+
+        // And the only one leaves nothing selected to look up
+        tabs.remove(0, 0);
+        assert_eq!(tabs.selected, 0);
+        assert_eq!(tabs.view(), TabView::Trades);
+        assert_eq!(tabs.selected_tkr(&[]), None);
+    }
+
+    #[test]
+    fn renders_into_any_width() {
+        for n in [0, 1, 5] {
+            let list = watchlist(n);
+            for selected in 0..n.max(1) {
+                let mut tabs = TkrTabs::default();
+                tabs.select(selected, n);
+                for width in 0..40 {
+                    let line = render(&tabs, &list, width);
+                    let title = format!("({}) TKR{}-PERP", selected + 1, selected);
+                    if n > 0 && width as usize >= title.len() + 4 {
+                        assert!(line.contains(&title), "{:?} at {}", line, width);
+                    }
                 }
             }
-
-            rows.push(
-                Row::new([
-                    Cell::new(
-                        DateTime::from_timestamp_millis(row_i.t)
-                            .unwrap()
-                            .with_timezone(&Local)
-                            .format("%H:%M:%S")
-                            .to_string(),
-                    ),
-                    Cell::new(format!("{:.2}", p_i)),   // last price
-                    Cell::new(format!("{:.4}", qty_i)), // 24h volume
-                    Cell::new(row_i.b.clone()).fg(bid_fg_color), // best bid
-                    Cell::new(row_i.k.clone()).fg(ask_fg_color), // best ask
-                ])
-                .bg(row_bg_color)
-                .fg(row_fg_color),
-            );
         }
+        // Scrolled to the selected tab, with markers for the hidden ones
+        let mut tabs = TkrTabs::default();
+        tabs.select(2, 5);
+        assert_eq!(render(&tabs, &watchlist(5), 20), "< (3) TKR2-PERP >   ");
+    }
 
-        Table::new(
-            rows,
-            [
-                Constraint::Length(12),
-                Constraint::Length(12),
-                Constraint::Length(12),
-                Constraint::Length(12),
-                Constraint::Length(12),
-            ],
-        )
-        .header(headers)
-        .block(block)
+    #[test]
+    fn renders_a_stale_selection() {
+        // The watchlist may shrink before the tabs hear of it
+        let mut tabs = TkrTabs::default();
+        tabs.select(4, 5);
+        let line = render(&tabs, &watchlist(2), 40);
+        assert!(line.contains("(2) TKR1-PERP"), "{:?}", line);
     }
 }
@@ -0,0 +1,110 @@
+use crate::config::Theme;
+use crate::models::{Side, TradeList};
+use chrono::{DateTime, Local};
+use ratatui::{
+    buffer::Buffer,
+    prelude::{Constraint, Rect},
+    style::Stylize,
+    widgets::{Block, Cell, Row, Table, Widget},
+};
+
+/// Fills sharing a millisecond and aggressor side, shown as one print
+struct Print {
+    timestamp: i64,
+    side: Side,
+    price: f64, // volume weighted
+    qty: f64,
+    fills: usize,
+}
+
+pub struct TradeTape<'a> {
+    trades: &'a TradeList,
+    min_qty: f64,
+    theme: &'a Theme,
+}
+
+impl<'a> TradeTape<'a> {
+    pub fn new(trades: &'a TradeList, min_qty: f64, theme: &'a Theme) -> Self {
+        Self {
+            trades,
+            min_qty,
+            theme,
+        }
+    }
+
+    fn prints(&self) -> Vec<Print> {
+        let mut prints: Vec<Print> = Vec::new();
+        for trade in self.trades.trades.iter_newest() {
+            match prints.last_mut() {
+                Some(print) if print.timestamp == trade.timestamp && print.side == trade.side => {
+                    let notional = print.price * print.qty + trade.price * trade.qty;
+                    print.qty += trade.qty;
+                    if print.qty > 0.0 {
+                        print.price = notional / print.qty;
+                    }
+                    print.fills += 1;
+                }
+                _ => prints.push(Print {
+                    timestamp: trade.timestamp,
+                    side: trade.side,
+                    price: trade.price,
+                    qty: trade.qty,
+                    fills: 1,
+                }),
+            }
+        }
+        prints.retain(|print| print.qty >= self.min_qty);
+        prints
+    }
+}
+
+impl<'a> Widget for TradeTape<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = if self.min_qty > 0.0 {
+            format!("Trades (qty >= {})", self.min_qty)
+        } else {
+            "Trades".to_string()
+        };
+        let block = Block::bordered().title(title);
+        let headers = Row::new(["Time", "Price", "Qty", "Side", "Fills"])
+            .bg(self.theme.header_bg)
+            .fg(self.theme.header_fg);
+
+        let rows: Vec<Row> = self
+            .prints()
+            .into_iter()
+            .take(area.height as usize)
+            .map(|print| {
+                let (side, color) = match print.side {
+                    Side::Buy => ("BUY", self.theme.up),
+                    Side::Sell => ("SELL", self.theme.down),
+                };
+                let time = DateTime::from_timestamp_millis(print.timestamp)
+                    .map(|t| t.with_timezone(&Local).format("%H:%M:%S%.3f").to_string())
+                    .unwrap_or_default();
+                Row::new([
+                    Cell::new(time),
+                    Cell::new(format!("{:.2}", print.price)),
+                    Cell::new(format!("{:.4}", print.qty)),
+                    Cell::new(side),
+                    Cell::new(print.fills.to_string()),
+                ])
+                .fg(color)
+            })
+            .collect();
+
+        Table::new(
+            rows,
+            [
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(6),
+                Constraint::Length(6),
+            ],
+        )
+        .header(headers)
+        .block(block)
+        .render(area, buf);
+    }
+}
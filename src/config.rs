@@ -0,0 +1,758 @@
+use crate::alerts::Rule;
+use crate::indicators::Study;
+use crate::models::Interval;
+use crate::sources::{binance, cryptocom};
+use anyhow::{anyhow, Context, Result};
+use ratatui::{
+    crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+    style::Color,
+};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Settings read from `config.toml`; every section is optional
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub endpoint: Endpoint,
+    pub watchlist: Watchlists,
+    pub buffers: Buffers,
+    pub chart: ChartConfig,
+    pub indicators: IndicatorConfig,
+    pub alerts: AlertConfig,
+    pub theme: Theme,
+    pub keys: KeyBindings,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Endpoint {
+    pub venue: String,        // "crypto.com" or "binance"
+    pub compare: Vec<String>, // venues streamed alongside for price comparison
+    pub ws_url: String,
+    pub binance_url: String, // spot, or wss://fstream.binance.com/stream for futures
+    pub instruments_url: String,
+}
+
+impl Endpoint {
+    /// Websocket url of `venue`
+    pub fn url(&self, venue: &str) -> &str {
+        match venue {
+            binance::VENUE => &self.binance_url,
+            _ => &self.ws_url,
+        }
+    }
+
+    /// The selected venue followed by the comparison venues
+    pub fn venues(&self) -> Vec<String> {
+        let mut venues = vec![self.venue.clone()];
+        for venue in self.compare.iter() {
+            if !venues.contains(venue) {
+                venues.push(venue.clone());
+            }
+        }
+        venues
+    }
+
+    pub fn url_mut(&mut self) -> &mut String {
+        match self.venue.as_str() {
+            binance::VENUE => &mut self.binance_url,
+            _ => &mut self.ws_url,
+        }
+    }
+}
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Self {
+            venue: cryptocom::VENUE.to_string(),
+            compare: Vec::new(),
+            ws_url: "wss://stream.crypto.com/exchange/v1/market".to_string(),
+            binance_url: "wss://stream.binance.com:9443/stream".to_string(),
+            instruments_url: "https://api.crypto.com/exchange/v1/public/get-instruments"
+                .to_string(),
+        }
+    }
+}
+
+/// Named watchlists, `active` picks the one loaded at startup
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Watchlists {
+    pub active: String,
+    pub lists: HashMap<String, Vec<String>>,
+}
+
+impl Default for Watchlists {
+    fn default() -> Self {
+        Self {
+            active: "default".to_string(),
+            lists: HashMap::from([(
+                "default".to_string(),
+                vec!["BTCUSD-PERP".to_string(), "ETHUSD-PERP".to_string()],
+            )]),
+        }
+    }
+}
+
+impl Watchlists {
+    pub fn active(&self) -> Vec<String> {
+        self.lists.get(&self.active).cloned().unwrap_or_default()
+    }
+}
+
+/// History kept per instrument
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Buffers {
+    pub tickers: usize,
+    pub trades: usize,
+    pub candles: usize,
+}
+
+impl Default for Buffers {
+    fn default() -> Self {
+        Self {
+            tickers: 1_000,
+            trades: 1_000,
+            candles: 500,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChartConfig {
+    #[serde(deserialize_with = "interval_from_str")]
+    pub interval: Interval,
+}
+
+/// Chart studies; `show` lists those drawn at startup
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct IndicatorConfig {
+    pub show: Vec<Study>,
+    pub sma: usize,
+    pub ema: usize,
+    pub bollinger: usize,
+    pub bollinger_k: f64, // band width in standard deviations
+    pub rsi: usize,
+    pub macd: [usize; 3], // fast, slow and signal EMA periods
+}
+
+impl Default for IndicatorConfig {
+    fn default() -> Self {
+        Self {
+            show: Vec::new(),
+            sma: 20,
+            ema: 50,
+            bollinger: 20,
+            bollinger_k: 2.0,
+            rsi: 14,
+            macd: [12, 26, 9],
+        }
+    }
+}
+
+impl IndicatorConfig {
+    /// Applies a setting typed in the Tui, e.g. "sma 50", "bollinger 20 2.5"
+    /// or "macd 12 26 9", and returns the study it belongs to
+    pub fn set(&mut self, input: &str) -> Result<Study> {
+        let mut words = input.split_whitespace();
+        let name = words.next().unwrap_or_default().to_ascii_lowercase();
+        let study = match name.as_str() {
+            "sma" => Study::Sma,
+            "ema" => Study::Ema,
+            "vwap" => Study::Vwap,
+            "bb" | "bollinger" => Study::Bollinger,
+            "rsi" => Study::Rsi,
+            "macd" => Study::Macd,
+            _ => return Err(anyhow!("unknown indicator {:?}", name)),
+        };
+        let args: Vec<&str> = words.collect();
+        let period = |i: usize| -> Result<usize> {
+            let arg = args.get(i).ok_or_else(|| anyhow!("missing period"))?;
+            arg.parse().map_err(|_| anyhow!("invalid period {:?}", arg))
+        };
+        let mut config = self.clone();
+        match study {
+            Study::Sma => config.sma = period(0)?,
+            Study::Ema => config.ema = period(0)?,
+            Study::Vwap => {}
+            Study::Bollinger => {
+                config.bollinger = period(0)?;
+                if let Some(k) = args.get(1) {
+                    config.bollinger_k = k.parse().map_err(|_| anyhow!("invalid width {:?}", k))?;
+                }
+            }
+            Study::Rsi => config.rsi = period(0)?,
+            Study::Macd => config.macd = [period(0)?, period(1)?, period(2)?],
+        }
+        config.validate()?;
+        *self = config;
+        Ok(study)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let [fast, slow, signal] = self.macd;
+        let periods = [
+            self.sma,
+            self.ema,
+            self.bollinger,
+            self.rsi,
+            fast,
+            slow,
+            signal,
+        ];
+        if periods.contains(&0) {
+            return Err(anyhow!("indicator periods must be at least 1"));
+        }
+        if fast >= slow {
+            return Err(anyhow!("macd fast period must be below the slow one"));
+        }
+        if self.bollinger_k.is_nan() || self.bollinger_k <= 0.0 {
+            return Err(anyhow!("bollinger_k must be above 0"));
+        }
+        Ok(())
+    }
+}
+
+/// Alert rules added at startup, e.g. "BTCUSD-PERP last > 70000"; rules
+/// added in the Tui are saved with their state in the alerts file instead
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertConfig {
+    pub rules: Vec<String>,
+    pub bell: bool,              // ring the terminal bell when one fires
+    pub webhook: Option<String>, // url each fired alert is POSTed to as JSON
+    pub command: Option<String>, // shell command run with ALERT_* variables set
+    pub retries: u32,            // after a failed delivery
+    pub timeout: u64,            // seconds per delivery attempt
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            bell: false,
+            webhook: None,
+            command: None,
+            retries: 3,
+            timeout: 10,
+        }
+    }
+}
+
+/// Colours accept names ("red") or hex ("#a6e3a1")
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub up: Color,
+    pub down: Color,
+    pub warn: Color,
+    pub header_bg: Color,
+    pub header_fg: Color,
+    pub highlight_fg: Color, // text drawn on up/down/warn backgrounds
+    pub axis: Color,
+    pub sma: Color, // indicator lines
+    pub ema: Color,
+    pub vwap: Color,
+    pub bands: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            up: Color::Rgb(166, 227, 161),
+            down: Color::Rgb(243, 139, 168),
+            warn: Color::Rgb(249, 226, 175),
+            header_bg: Color::Rgb(205, 214, 244),
+            header_fg: Color::Rgb(17, 17, 27),
+            highlight_fg: Color::Rgb(17, 17, 27),
+            axis: Color::Gray,
+            sma: Color::Rgb(137, 180, 250),
+            ema: Color::Rgb(203, 166, 247),
+            vwap: Color::Rgb(250, 179, 135),
+            bands: Color::Rgb(148, 226, 213),
+        }
+    }
+}
+
+impl Theme {
+    /// Built-in themes selectable with `--theme`
+    pub fn preset(name: &str) -> Result<Self> {
+        match name {
+            "catppuccin" => Ok(Self::default()),
+            "classic" => Ok(Self {
+                up: Color::Green,
+                down: Color::Red,
+                warn: Color::Yellow,
+                header_bg: Color::Blue,
+                header_fg: Color::White,
+                highlight_fg: Color::Black,
+                axis: Color::Gray,
+                sma: Color::Blue,
+                ema: Color::Magenta,
+                vwap: Color::Yellow,
+                bands: Color::Cyan,
+            }),
+            "mono" => Ok(Self {
+                up: Color::White,
+                down: Color::Gray,
+                warn: Color::White,
+                header_bg: Color::White,
+                header_fg: Color::Black,
+                highlight_fg: Color::Black,
+                axis: Color::DarkGray,
+                sma: Color::White,
+                ema: Color::Gray,
+                vwap: Color::White,
+                bands: Color::DarkGray,
+            }),
+            _ => Err(anyhow!(
+                "unknown theme {:?}, expected catppuccin, classic or mono",
+                name
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Quit,
+    NextTab,
+    PrevTab,
+    Add,
+    Remove,
+    TradesView,
+    BookView,
+    CompareView,
+    NextInterval,
+    PrevInterval,
+    FilterUp,
+    FilterDown,
+    Step,
+    Toggle(Study),
+    Indicators,
+    AlertsView,
+    Alert,
+    PortfolioView,
+    Position,
+}
+
+/// Keys for each action, e.g. `quit = ["q", "esc"]`. Tabs 1-9 are fixed.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub quit: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub next_tab: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub prev_tab: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub add: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub remove: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub trades_view: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub book_view: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub compare_view: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub next_interval: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub prev_interval: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub filter_up: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub filter_down: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub step: Vec<KeyCode>, // next frame in `replay --step`
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub sma: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub ema: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub vwap: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub bollinger: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub rsi: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub macd: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub indicators: Vec<KeyCode>, // prompt for indicator periods
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub alerts_view: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub alert: Vec<KeyCode>, // prompt to add, arm, disarm or delete alerts
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub portfolio_view: Vec<KeyCode>,
+    #[serde(deserialize_with = "keys_from_strs")]
+    pub position: Vec<KeyCode>, // prompt to add or delete positions
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            quit: vec![KeyCode::Char('q')],
+            next_tab: vec![KeyCode::Tab, KeyCode::Right],
+            prev_tab: vec![KeyCode::BackTab, KeyCode::Left],
+            add: vec![KeyCode::Char('a')],
+            remove: vec![KeyCode::Char('d')],
+            trades_view: vec![KeyCode::Char('t')],
+            book_view: vec![KeyCode::Char('b')],
+            compare_view: vec![KeyCode::Char('c')],
+            next_interval: vec![KeyCode::Char('i')],
+            prev_interval: vec![KeyCode::Char('I')],
+            filter_up: vec![KeyCode::Char('+')],
+            filter_down: vec![KeyCode::Char('-')],
+            step: vec![KeyCode::Char('.')],
+            sma: vec![KeyCode::Char('s')],
+            ema: vec![KeyCode::Char('e')],
+            vwap: vec![KeyCode::Char('v')],
+            bollinger: vec![KeyCode::Char('B')],
+            rsi: vec![KeyCode::Char('r')],
+            macd: vec![KeyCode::Char('m')],
+            indicators: vec![KeyCode::Char('p')],
+            alerts_view: vec![KeyCode::Char('l')],
+            alert: vec![KeyCode::Char('n')],
+            portfolio_view: vec![KeyCode::Char('w')],
+            position: vec![KeyCode::Char('o')],
+        }
+    }
+}
+
+impl KeyBindings {
+    fn all(&self) -> [(Action, &Vec<KeyCode>); 24] {
+        [
+            (Action::Quit, &self.quit),
+            (Action::NextTab, &self.next_tab),
+            (Action::PrevTab, &self.prev_tab),
+            (Action::Add, &self.add),
+            (Action::Remove, &self.remove),
+            (Action::TradesView, &self.trades_view),
+            (Action::BookView, &self.book_view),
+            (Action::CompareView, &self.compare_view),
+            (Action::NextInterval, &self.next_interval),
+            (Action::PrevInterval, &self.prev_interval),
+            (Action::FilterUp, &self.filter_up),
+            (Action::FilterDown, &self.filter_down),
+            (Action::Step, &self.step),
+            (Action::Toggle(Study::Sma), &self.sma),
+            (Action::Toggle(Study::Ema), &self.ema),
+            (Action::Toggle(Study::Vwap), &self.vwap),
+            (Action::Toggle(Study::Bollinger), &self.bollinger),
+            (Action::Toggle(Study::Rsi), &self.rsi),
+            (Action::Toggle(Study::Macd), &self.macd),
+            (Action::Indicators, &self.indicators),
+            (Action::AlertsView, &self.alerts_view),
+            (Action::Alert, &self.alert),
+            (Action::PortfolioView, &self.portfolio_view),
+            (Action::Position, &self.position),
+        ]
+    }
+
+    /// Bindings are plain keys, so Ctrl and Alt chords match none of them.
+    /// Ctrl-C quits: in raw mode it arrives as a key rather than SIGINT.
+    pub fn action(&self, key: KeyEvent) -> Option<Action> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Some(Action::Quit);
+        }
+        if key
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+        {
+            return None;
+        }
+        self.all()
+            .into_iter()
+            .find(|(_, keys)| keys.contains(&key.code))
+            .map(|(action, _)| action)
+    }
+
+    /// First key bound to `action`, for hints in the status bar
+    pub fn hint(&self, action: Action) -> String {
+        self.all()
+            .into_iter()
+            .find(|(a, _)| *a == action)
+            .and_then(|(_, keys)| keys.first().copied())
+            .map(key_name)
+            .unwrap_or_default()
+    }
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/crypto_tui/config.toml` or the platform equivalent
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("crypto_tui").join("config.toml"))
+    }
+
+    /// Loads `path` if given, else the default path if it exists, else defaults
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let config = match path {
+            Some(path) => Self::from_file(path)?,
+            None => match Self::default_path() {
+                Some(path) if path.exists() => Self::from_file(&path)?,
+                _ => Self::default(),
+            },
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading config {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("parsing config {}", path.display()))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for venue in self.endpoint.venues() {
+            if venue != cryptocom::VENUE && venue != binance::VENUE {
+                return Err(anyhow!(
+                    "venues must be {} or {}: {}",
+                    cryptocom::VENUE,
+                    binance::VENUE,
+                    venue
+                ));
+            }
+        }
+        for (key, url) in [
+            ("ws_url", &self.endpoint.ws_url),
+            ("binance_url", &self.endpoint.binance_url),
+        ] {
+            if !(url.starts_with("ws://") || url.starts_with("wss://")) {
+                return Err(anyhow!(
+                    "endpoint.{} must be a ws:// or wss:// url: {}",
+                    key,
+                    url
+                ));
+            }
+        }
+
+        let watchlist = self
+            .watchlist
+            .lists
+            .get(&self.watchlist.active)
+            .ok_or_else(|| {
+                anyhow!(
+                    "watchlist.active names a missing list: {}",
+                    self.watchlist.active
+                )
+            })?;
+        if watchlist.is_empty() {
+            return Err(anyhow!("watchlist {} is empty", self.watchlist.active));
+        }
+        for (name, list) in self.watchlist.lists.iter() {
+            for tkr in list {
+                validate_instrument(tkr).with_context(|| format!("in watchlist {}", name))?;
+            }
+        }
+
+        if self.buffers.tickers == 0 || self.buffers.trades == 0 || self.buffers.candles == 0 {
+            return Err(anyhow!("buffers must hold at least one entry"));
+        }
+        self.indicators.validate()?;
+        for rule in self.alerts.rules.iter() {
+            Rule::parse(rule).context("in alerts.rules")?;
+        }
+        if let Some(url) = &self.alerts.webhook {
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return Err(anyhow!(
+                    "alerts.webhook must be an http:// or https:// url: {}",
+                    url
+                ));
+            }
+        }
+        if self.alerts.timeout == 0 {
+            return Err(anyhow!("alerts.timeout must be at least 1 second"));
+        }
+
+        let mut seen: HashMap<KeyCode, Action> = HashMap::new();
+        for (action, keys) in self.keys.all() {
+            for key in keys {
+                if let KeyCode::Char('1'..='9') = key {
+                    return Err(anyhow!("keys 1-9 are reserved for tabs: {:?}", action));
+                }
+                if let Some(other) = seen.insert(*key, action) {
+                    return Err(anyhow!(
+                        "key {} bound to both {:?} and {:?}",
+                        key_name(*key),
+                        other,
+                        action
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Instrument names are upper case letters, digits, '-' and '_'
+pub fn validate_instrument(tkr: &str) -> Result<()> {
+    let valid = !tkr.is_empty()
+        && tkr
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!("invalid instrument name: {:?}", tkr))
+    }
+}
+
+fn interval_from_str<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Interval, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    Interval::ALL
+        .into_iter()
+        .find(|interval| interval.label() == s)
+        .ok_or_else(|| serde::de::Error::custom(format!("unknown interval {:?}", s)))
+}
+
+fn keys_from_strs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<KeyCode>, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    names
+        .iter()
+        .map(|name| parse_key(name).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn parse_key(name: &str) -> Result<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c));
+    }
+    match name.to_ascii_lowercase().as_str() {
+        "tab" => Ok(KeyCode::Tab),
+        "backtab" => Ok(KeyCode::BackTab),
+        "left" => Ok(KeyCode::Left),
+        "right" => Ok(KeyCode::Right),
+        "up" => Ok(KeyCode::Up),
+        "down" => Ok(KeyCode::Down),
+        "esc" => Ok(KeyCode::Esc),
+        "enter" => Ok(KeyCode::Enter),
+        "space" => Ok(KeyCode::Char(' ')),
+        _ => Err(anyhow!("unknown key {:?}", name)),
+    }
+}
+
+fn key_name(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::BackTab => "backtab".to_string(),
+        KeyCode::Left => "left".to_string(),
+        KeyCode::Right => "right".to_string(),
+        KeyCode::Up => "up".to_string(),
+        KeyCode::Down => "down".to_string(),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::Enter => "enter".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn ctrl_c_quits_and_chords_match_no_binding() {
+        let keys = KeyBindings::default();
+        let c = KeyCode::Char('c');
+        assert_eq!(
+            keys.action(key(c, KeyModifiers::NONE)),
+            Some(Action::CompareView)
+        );
+        assert_eq!(
+            keys.action(key(c, KeyModifiers::CONTROL)),
+            Some(Action::Quit)
+        );
+        assert_eq!(keys.action(key(c, KeyModifiers::ALT)), None);
+        assert_eq!(
+            keys.action(key(KeyCode::Char('q'), KeyModifiers::CONTROL)),
+            None
+        );
+        // Shifted letters are bindings of their own
+        assert_eq!(
+            keys.action(key(KeyCode::Char('B'), KeyModifiers::SHIFT)),
+            Some(Action::Toggle(Study::Bollinger))
+        );
+    }
+
+    fn invalid(toml: &str) -> String {
+        let config: Config = toml::from_str(toml).unwrap();
+        format!("{:#}", config.validate().unwrap_err())
+    }
+
+    #[test]
+    fn loads_the_example_config() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/config.example.toml");
+        let config = Config::load(Some(Path::new(path))).unwrap();
+        assert_eq!(config.endpoint.venue, cryptocom::VENUE);
+        assert_eq!(
+            config.watchlist.lists["default"],
+            ["BTCUSD-PERP", "ETHUSD-PERP"]
+        );
+        assert_eq!(config.keys.hint(Action::Quit), "q");
+        Config::default().validate().unwrap();
+    }
+
+    #[test]
+    fn rejects_clashing_keys() {
+        let error = invalid("[keys]\nquit = [\"a\"]");
+        assert_eq!(error, "key a bound to both Quit and Add");
+        let error = invalid("[keys]\nsma = [\"3\"]");
+        assert_eq!(error, "keys 1-9 are reserved for tabs: Toggle(Sma)");
+    }
+
+    #[test]
+    fn rejects_bad_urls() {
+        let error = invalid("[endpoint]\nws_url = \"https://stream.crypto.com\"");
+        assert!(error.starts_with("endpoint.ws_url must be"), "{}", error);
+        let error = invalid("[endpoint]\nbinance_url = \"stream.binance.com\"");
+        assert!(
+            error.starts_with("endpoint.binance_url must be"),
+            "{}",
+            error
+        );
+        let error = invalid("[alerts]\nwebhook = \"ftp://hooks.example.com\"");
+        assert!(error.starts_with("alerts.webhook must be"), "{}", error);
+        let error = invalid("[endpoint]\ncompare = [\"kraken\"]");
+        assert!(error.starts_with("venues must be"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_bad_watchlists() {
+        let error = invalid("[watchlist.lists]\ndefault = []");
+        assert_eq!(error, "watchlist default is empty");
+        let error = invalid("[watchlist]\nactive = \"alts\"");
+        assert_eq!(error, "watchlist.active names a missing list: alts");
+        let error = invalid("[watchlist.lists]\ndefault = [\"btc usd\"]");
+        assert_eq!(
+            error,
+            "in watchlist default: invalid instrument name: \"btc usd\""
+        );
+    }
+
+    #[test]
+    fn rejects_empty_buffers_and_limits() {
+        for buffer in ["tickers", "trades", "candles"] {
+            let error = invalid(&format!("[buffers]\n{} = 0", buffer));
+            assert_eq!(error, "buffers must hold at least one entry");
+        }
+        let error = invalid("[alerts]\ntimeout = 0");
+        assert_eq!(error, "alerts.timeout must be at least 1 second");
+        let error = invalid("[alerts]\nrules = [\"BTCUSD-PERP last >= 1\"]");
+        assert!(error.starts_with("in alerts.rules"), "{}", error);
+    }
+}
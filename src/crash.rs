@@ -0,0 +1,128 @@
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use cli_log::*;
+use ratatui::crossterm::{
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    ExecutableCommand,
+};
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::future::Future;
+use std::io::stdout;
+use std::panic;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+const RECENT_FRAMES: usize = 50;
+
+// Set while the Tui owns the terminal, so only then is it handed back
+static TUI_ACTIVE: AtomicBool = AtomicBool::new(false);
+static RECENT: Mutex<VecDeque<(i64, String, String)>> = Mutex::new(VecDeque::new());
+
+/// Keeps a received frame for the crash report
+pub fn remember(venue: &str, frame: &str) {
+    if let Ok(mut recent) = RECENT.lock() {
+        if recent.len() == RECENT_FRAMES {
+            recent.pop_front();
+        }
+        recent.push_back((
+            Utc::now().timestamp_millis(),
+            venue.to_string(),
+            frame.to_string(),
+        ));
+    }
+}
+
+pub fn enter_terminal() -> Result<()> {
+    stdout().execute(EnterAlternateScreen)?;
+    enable_raw_mode()?;
+    TUI_ACTIVE.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Leaves raw mode and the alternate screen; a no-op unless the Tui is up
+pub fn restore_terminal() {
+    if TUI_ACTIVE.swap(false, Ordering::SeqCst) {
+        let _ = disable_raw_mode();
+        let _ = stdout().execute(LeaveAlternateScreen);
+    }
+}
+
+/// On a panic, hands the terminal back before the message is printed and
+/// writes a crash report with the frames received last. The Tui runs on the
+/// main thread; a panic elsewhere only ends a spawned task, so it is logged
+/// and the Tui keeps the terminal.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let current = thread::current();
+        if current.name() != Some("main") {
+            error!(
+                "Panic in {}: {}",
+                current.name().unwrap_or("a thread"),
+                info
+            );
+            if !TUI_ACTIVE.load(Ordering::SeqCst) {
+                default_hook(info);
+            }
+            return;
+        }
+        restore_terminal();
+        error!("Panic: {}", info);
+        logger().flush();
+        match write_report(&info.to_string()) {
+            Ok(path) => eprintln!("Crash report written to {}", path.display()),
+            Err(e) => eprintln!("Crash report failed: {}", e),
+        }
+        default_hook(info);
+    }));
+}
+
+/// Spawns `task`, logging it if it panics rather than letting it end
+/// unnoticed
+pub fn spawn<F>(name: &'static str, task: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let handle = tokio::spawn(task);
+    tokio::spawn(async move {
+        if let Err(e) = handle.await {
+            if e.is_panic() {
+                error!("The {} task panicked: {}", name, e);
+            }
+        }
+    });
+}
+
+fn write_report(reason: &str) -> Result<PathBuf> {
+    let now = Utc::now();
+    let mut report = String::new();
+    writeln!(
+        report,
+        "crypto_tui {} crashed at {}",
+        env!("CARGO_PKG_VERSION"),
+        now
+    )?;
+    writeln!(report, "{}\n", reason)?;
+    writeln!(report, "{}", Backtrace::force_capture())?;
+
+    writeln!(report, "Last frames received, oldest first:")?;
+    if let Ok(recent) = RECENT.lock() {
+        for (t, venue, frame) in recent.iter() {
+            let time = Utc.timestamp_millis_opt(*t).single().unwrap_or(now);
+            writeln!(report, "{} {} {}", time.format("%T%.3f"), venue, frame)?;
+        }
+    }
+
+    let dir = dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("crypto_tui");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("crash-{}.txt", now.format("%Y%m%d-%H%M%S")));
+    fs::write(&path, report)?;
+    Ok(path)
+}
@@ -0,0 +1,333 @@
+use crate::alerts::Fired;
+use crate::config::AlertConfig;
+use crate::crash;
+use crate::feed::FeedTx;
+use crate::models::WsEvent;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use cli_log::*;
+use serde::Serialize;
+use std::future::Future;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::{sleep, timeout};
+
+const RETRY_BASE: Duration = Duration::from_secs(1);
+
+/// Outcome of handing one fired alert to one hook
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub time: i64,          // when it was delivered or given up on
+    pub hook: &'static str, // "webhook" or "command"
+    pub rule: String,
+    pub attempts: u32,
+    pub error: Option<String>, // the last failure if it never got through
+}
+
+/// JSON posted to the webhook; `text` is what chat tools display
+#[derive(Serialize)]
+struct Payload<'a> {
+    text: String,
+    #[serde(flatten)]
+    alert: &'a Fired,
+}
+
+fn text(fired: &Fired) -> String {
+    format!("ALERT {} ({})", fired.rule, fired.value)
+}
+
+/// Passes fired alerts on to a webhook and/or a shell command in the
+/// background, retrying failures, and reports each outcome to the Tui
+pub struct Hooks {
+    client: reqwest::Client,
+    webhook: Option<String>,
+    command: Option<String>,
+    retries: u32,
+    timeout: Duration,
+    tx: FeedTx,
+}
+
+impl Hooks {
+    /// None when no hook is configured
+    pub fn new(config: &AlertConfig, tx: FeedTx) -> Result<Option<Self>> {
+        if config.webhook.is_none() && config.command.is_none() {
+            return Ok(None);
+        }
+        let timeout = Duration::from_secs(config.timeout);
+        Ok(Some(Self {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            webhook: config.webhook.clone(),
+            command: config.command.clone(),
+            retries: config.retries,
+            timeout,
+            tx,
+        }))
+    }
+
+    pub fn deliver(&self, fired: &Fired) {
+        if let Some(url) = &self.webhook {
+            let (client, url) = (self.client.clone(), url.clone());
+            let body = serde_json::to_string(&Payload {
+                text: text(fired),
+                alert: fired,
+            })
+            .unwrap_or_default();
+            self.spawn("webhook", fired, move || {
+                post(client.clone(), url.clone(), body.clone())
+            });
+        }
+        if let Some(command) = &self.command {
+            let (command, fired_copy, limit) = (command.clone(), fired.clone(), self.timeout);
+            self.spawn("command", fired, move || {
+                run(command.clone(), fired_copy.clone(), limit)
+            });
+        }
+    }
+
+    /// Runs `attempt` until it succeeds or the retries run out
+    fn spawn<F, Fut>(&self, hook: &'static str, fired: &Fired, mut attempt: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send,
+    {
+        let (tx, retries, rule) = (self.tx.clone(), self.retries, fired.rule.clone());
+        crash::spawn(hook, async move {
+            let mut attempts = 0;
+            let mut delay = RETRY_BASE;
+            let error = loop {
+                attempts += 1;
+                match attempt().await {
+                    Ok(()) => break None,
+                    Err(e) if attempts > retries => break Some(e.to_string()),
+                    Err(e) => {
+                        debug!("Alert {} attempt {} failed: {}", hook, attempts, e);
+                        sleep(delay).await;
+                        delay *= 2;
+                    }
+                }
+            };
+            match &error {
+                Some(e) => info!("Alert {} gave up on {}: {}", hook, rule, e),
+                None => info!("Alert {} delivered {}", hook, rule),
+            }
+            let _ = tx.send(WsEvent::Delivery(Delivery {
+                time: Utc::now().timestamp_millis(),
+                hook,
+                rule,
+                attempts,
+                error,
+            }));
+        });
+    }
+}
+
+async fn post(client: reqwest::Client, url: String, body: String) -> Result<()> {
+    client
+        .post(&url)
+        .header("content-type", "application/json")
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Runs `command` in the shell with the alert in ALERT_* variables
+async fn run(command: String, fired: Fired, limit: Duration) -> Result<()> {
+    let mut cmd = shell(&command);
+    cmd.env("ALERT_TEXT", text(&fired))
+        .env("ALERT_TKR", &fired.tkr)
+        .env("ALERT_RULE", &fired.rule)
+        .env("ALERT_VALUE", &fired.value)
+        .env("ALERT_TIME", fired.time.to_string())
+        // Its output would draw over the Tui
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true); // on a timeout; only the shell, not what it started
+    let output = timeout(limit, cmd.output())
+        .await
+        .map_err(|_| anyhow!("timed out after {}s", limit.as_secs()))??;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.lines().last() {
+        Some(line) => Err(anyhow!("{}: {}", output.status, line)),
+        None => Err(anyhow!("{}", output.status)),
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::{self, FeedRx};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    fn fired() -> Fired {
+        Fired {
+            time: 1_760_000_000_000,
+            tkr: "BTCUSD-PERP".to_string(),
+            rule: "BTCUSD-PERP last > 70000".to_string(),
+            value: "70012.5".to_string(),
+        }
+    }
+
+    /// Answers each request with the next of `statuses`, passing on the
+    /// bodies received
+    async fn http_server(statuses: Vec<u16>) -> (String, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                let body = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                        continue;
+                    };
+                    let length: usize = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length: "))
+                        .map_or(0, |n| n.parse().unwrap());
+                    if body.len() >= length {
+                        break body.to_string();
+                    }
+                };
+                let _ = tx.send(body);
+                let response = format!(
+                    "HTTP/1.1 {} X\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    fn hooks(config: AlertConfig) -> (Hooks, FeedRx) {
+        let (tx, rx) = feed::channel();
+        (Hooks::new(&config, tx).unwrap().unwrap(), rx)
+    }
+
+    async fn delivery(rx: &mut FeedRx) -> Delivery {
+        loop {
+            let event = timeout(Duration::from_secs(10), rx.recv()).await.unwrap();
+            if let Some(WsEvent::Delivery(delivery)) = event {
+                return delivery;
+            }
+        }
+    }
+
+    fn webhook(url: String, retries: u32) -> AlertConfig {
+        AlertConfig {
+            webhook: Some(url),
+            retries,
+            timeout: 5,
+            ..AlertConfig::default()
+        }
+    }
+
+    fn command(command: &str, timeout: u64) -> AlertConfig {
+        AlertConfig {
+            command: Some(command.to_string()),
+            retries: 0,
+            timeout,
+            ..AlertConfig::default()
+        }
+    }
+
+    #[test]
+    fn needs_a_hook() {
+        let (tx, _rx) = feed::channel();
+        assert!(Hooks::new(&AlertConfig::default(), tx).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn posts_to_the_webhook() {
+        let (url, mut bodies) = http_server(vec![204]).await;
+        let (hooks, mut rx) = hooks(webhook(url, 3));
+        hooks.deliver(&fired());
+
+        let delivery = delivery(&mut rx).await;
+        assert_eq!((delivery.hook, delivery.attempts), ("webhook", 1));
+        assert_eq!(delivery.error, None);
+        let body: serde_json::Value = serde_json::from_str(&bodies.recv().await.unwrap()).unwrap();
+        assert_eq!(body["text"], "ALERT BTCUSD-PERP last > 70000 (70012.5)");
+        assert_eq!(body["tkr"], "BTCUSD-PERP");
+        assert_eq!(body["value"], "70012.5");
+        assert_eq!(body["time"], 1_760_000_000_000i64);
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let (url, mut bodies) = http_server(vec![500, 200]).await;
+        let (hooks, mut rx) = hooks(webhook(url, 3));
+        hooks.deliver(&fired());
+
+        let delivery = delivery(&mut rx).await;
+        assert_eq!((delivery.attempts, delivery.error), (2, None));
+        assert_eq!(bodies.recv().await, bodies.recv().await);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_retries() {
+        let (url, _bodies) = http_server(vec![503, 503]).await;
+        let (hooks, mut rx) = hooks(webhook(url, 1));
+        hooks.deliver(&fired());
+
+        let delivery = delivery(&mut rx).await;
+        assert_eq!(delivery.attempts, 2);
+        assert!(delivery.error.unwrap().contains("503"));
+    }
+
+    #[tokio::test]
+    async fn runs_the_command_with_the_alert() {
+        let (hooks, mut rx) = hooks(command(
+            r#"[ "$ALERT_TKR" = BTCUSD-PERP ] && [ "$ALERT_VALUE" = 70012.5 ] && [ "$ALERT_TIME" = 1760000000000 ]"#,
+            5,
+        ));
+        hooks.deliver(&fired());
+        let delivery = delivery(&mut rx).await;
+        assert_eq!(delivery.hook, "command");
+        assert_eq!(delivery.error, None);
+    }
+
+    #[tokio::test]
+    async fn reports_a_failing_command() {
+        let (hooks, mut rx) = hooks(command("echo oops >&2; exit 3", 5));
+        hooks.deliver(&fired());
+        let error = delivery(&mut rx).await.error.unwrap();
+        assert!(error.ends_with("3: oops"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn times_out_a_slow_command() {
+        let (hooks, mut rx) = hooks(command("exec sleep 5", 1));
+        hooks.deliver(&fired());
+        let delivery = delivery(&mut rx).await;
+        assert_eq!(delivery.error.as_deref(), Some("timed out after 1s"));
+    }
+}
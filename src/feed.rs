@@ -0,0 +1,367 @@
+use crate::models::{MarketKey, TkrResult, TradeResult, WsEvent};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio::sync::Notify;
+
+const TRADE_QUEUE: usize = 1_024;
+const EVENT_QUEUE: usize = 256; // books, status, acks
+const TKR_BACKLOG: usize = 256; // ticker updates held per market
+
+/// Tickers waiting for the Tui, merged per market
+#[derive(Default)]
+struct Latest {
+    tickers: Mutex<HashMap<MarketKey, TkrResult>>,
+    notify: Notify,
+    taken: Notify, // the Tui took a market's tickers
+    dropped_tkrs: AtomicU64,
+    dropped_trades: AtomicU64,
+    dropped_events: AtomicU64,
+}
+
+impl Latest {
+    /// Appends `result` to the market's pending updates if they have room
+    /// for it, or unconditionally when `force`, shedding the oldest. Gives
+    /// `result` back when it had to wait.
+    fn merge(&self, result: TkrResult, force: bool) -> Option<TkrResult> {
+        let Ok(mut tickers) = self.tickers.lock() else {
+            return None;
+        };
+        let key = MarketKey::new(&result.venue, &result.tkr);
+        match tickers.get_mut(&key) {
+            Some(pending) if !force && pending.data.len() + result.data.len() > TKR_BACKLOG => {
+                return Some(result)
+            }
+            Some(pending) => {
+                pending.data.extend(result.data);
+                let excess = pending.data.len().saturating_sub(TKR_BACKLOG);
+                pending.data.drain(..excess);
+                self.dropped_tkrs
+                    .fetch_add(excess as u64, Ordering::Relaxed);
+            }
+            None => {
+                tickers.insert(key, result);
+            }
+        }
+        self.notify.notify_one();
+        None
+    }
+}
+
+/// Messages lost because the Tui fell behind
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Dropped {
+    pub tkrs: u64, // ticker updates
+    pub trades: u64,
+    pub events: u64,
+}
+
+/// Market data path from the sources to the Tui that never makes a source
+/// wait, so a slow frame can't hold up reading the socket or answering
+/// heartbeats. Ticker updates are merged per market into one message, up to
+/// a backlog; trades and other events go through bounded queues. Whatever
+/// doesn't fit is counted as dropped.
+pub fn channel() -> (FeedTx, FeedRx) {
+    let latest = Arc::new(Latest::default());
+    let (trade_tx, trade_rx) = mpsc::channel(TRADE_QUEUE);
+    let (event_tx, event_rx) = mpsc::channel(EVENT_QUEUE);
+    (
+        FeedTx {
+            latest: latest.clone(),
+            trades: trade_tx,
+            events: event_tx,
+        },
+        FeedRx {
+            latest,
+            trades: Some(trade_rx),
+            events: Some(event_rx),
+        },
+    )
+}
+
+#[derive(Clone)]
+pub struct FeedTx {
+    latest: Arc<Latest>,
+    trades: Sender<TradeResult>,
+    events: Sender<WsEvent>,
+}
+
+impl FeedTx {
+    /// Hands over `event` without waiting; errors once the receiver is gone
+    pub fn send(&self, event: WsEvent) -> Result<()> {
+        if self.is_closed() {
+            return Err(anyhow!("feed receiver closed"));
+        }
+        match event {
+            WsEvent::Tkr(result) => {
+                self.latest.merge(result, true);
+            }
+            WsEvent::Trade(result) => {
+                if let Err(TrySendError::Full(_)) = self.trades.try_send(result) {
+                    self.latest.dropped_trades.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            event => {
+                if let Err(TrySendError::Full(_)) = self.events.try_send(event) {
+                    self.latest.dropped_events.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands over `event`, waiting for room instead of dropping anything;
+    /// for sources with no socket to keep up with, like a replay
+    pub async fn send_wait(&self, event: WsEvent) -> Result<()> {
+        let closed = || anyhow!("feed receiver closed");
+        match event {
+            WsEvent::Tkr(mut result) => loop {
+                let taken = self.latest.taken.notified();
+                tokio::pin!(taken);
+                taken.as_mut().enable();
+                if self.is_closed() {
+                    return Err(closed());
+                }
+                match self.latest.merge(result, false) {
+                    Some(waiting) => result = waiting,
+                    None => return Ok(()),
+                }
+                tokio::select! {
+                    _ = taken => {}
+                    _ = self.closed() => return Err(closed()),
+                }
+            },
+            WsEvent::Trade(result) => self.trades.send(result).await.map_err(|_| closed()),
+            event => self.events.send(event).await.map_err(|_| closed()),
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.events.is_closed()
+    }
+
+    /// Completes once the receiver is dropped
+    pub async fn closed(&self) {
+        self.events.closed().await
+    }
+}
+
+pub struct FeedRx {
+    latest: Arc<Latest>,
+    trades: Option<Receiver<TradeResult>>, // None once every sender is gone
+    events: Option<Receiver<WsEvent>>,
+}
+
+impl FeedRx {
+    /// Next message of any kind without waiting
+    pub fn try_recv(&mut self) -> Option<WsEvent> {
+        if let Some(event) = self.events.as_mut().and_then(|rx| rx.try_recv().ok()) {
+            return Some(event);
+        }
+        if let Some(result) = self.trades.as_mut().and_then(|rx| rx.try_recv().ok()) {
+            return Some(WsEvent::Trade(result));
+        }
+        let mut tickers = self.latest.tickers.lock().ok()?;
+        let key = tickers.keys().next()?.clone();
+        let result = tickers.remove(&key);
+        self.latest.taken.notify_waiters();
+        result.map(WsEvent::Tkr)
+    }
+
+    /// Waits for the next message; None after every sender has gone
+    pub async fn recv(&mut self) -> Option<WsEvent> {
+        loop {
+            if let Some(event) = self.try_recv() {
+                return Some(event);
+            }
+            if self.events.is_none() && self.trades.is_none() {
+                return None;
+            }
+            tokio::select! {
+                _ = self.latest.notify.notified() => {}
+                event = recv_or_pending(&mut self.events) => match event {
+                    Some(event) => return Some(event),
+                    None => self.events = None,
+                },
+                result = recv_or_pending(&mut self.trades) => match result {
+                    Some(result) => return Some(WsEvent::Trade(result)),
+                    None => self.trades = None,
+                },
+            }
+        }
+    }
+
+    pub fn dropped(&self) -> Dropped {
+        Dropped {
+            tkrs: self.latest.dropped_tkrs.load(Ordering::Relaxed),
+            trades: self.latest.dropped_trades.load(Ordering::Relaxed),
+            events: self.latest.dropped_events.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// `recv` on a queue that may already have closed
+async fn recv_or_pending<T>(rx: &mut Option<Receiver<T>>) -> Option<T> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TkrData;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn tkr(venue: &str, name: &str, t: i64) -> WsEvent {
+        let data = TkrData {
+            t,
+            ..TkrData::default()
+        };
+        WsEvent::Tkr(TkrResult::new(venue, name, "ticker", vec![data]))
+    }
+
+    fn trade() -> WsEvent {
+        WsEvent::Trade(TradeResult::new("binance", "BTCUSDT", "trade", vec![]))
+    }
+
+    fn times(event: Option<WsEvent>) -> (String, Vec<i64>) {
+        let Some(WsEvent::Tkr(result)) = event else {
+            panic!("expected a ticker, got {:?}", event);
+        };
+        (
+            result.venue,
+            result.data.iter().map(|data| data.t).collect(),
+        )
+    }
+
+    #[test]
+    fn merges_tickers_per_market() {
+        let (tx, mut rx) = channel();
+        for t in 1..=3 {
+            tx.send(tkr("binance", "BTCUSDT", t)).unwrap();
+        }
+        tx.send(tkr("cryptocom", "BTCUSDT", 7)).unwrap();
+
+        let mut got = vec![times(rx.try_recv()), times(rx.try_recv())];
+        got.sort();
+        assert_eq!(
+            got,
+            vec![
+                ("binance".to_string(), vec![1, 2, 3]),
+                ("cryptocom".to_string(), vec![7])
+            ]
+        );
+        assert!(rx.try_recv().is_none());
+
+        // Past the backlog the oldest updates go
+        for t in 0..TKR_BACKLOG as i64 + 10 {
+            tx.send(tkr("binance", "BTCUSDT", t)).unwrap();
+        }
+        let (_, kept) = times(rx.try_recv());
+        assert_eq!(kept.len(), TKR_BACKLOG);
+        assert_eq!(kept[0], 10);
+        assert_eq!(rx.dropped().tkrs, 10);
+    }
+
+    #[test]
+    fn counts_what_the_full_queues_drop() {
+        let (tx, mut rx) = channel();
+        for _ in 0..TRADE_QUEUE + 5 {
+            tx.send(trade()).unwrap();
+        }
+        for _ in 0..EVENT_QUEUE + 3 {
+            tx.send(WsEvent::Catalogue(vec![])).unwrap();
+        }
+        assert_eq!(
+            rx.dropped(),
+            Dropped {
+                tkrs: 0,
+                trades: 5,
+                events: 3
+            }
+        );
+        // Events come ahead of trades
+        assert!(matches!(rx.try_recv(), Some(WsEvent::Catalogue(_))));
+        let mut received = 1;
+        while rx.try_recv().is_some() {
+            received += 1;
+        }
+        assert_eq!(received, TRADE_QUEUE + EVENT_QUEUE);
+    }
+
+    #[tokio::test]
+    async fn recv_ends_once_the_senders_are_gone() {
+        let (tx, mut rx) = channel();
+        let tx2 = tx.clone();
+        tx.send(trade()).unwrap();
+        tx2.send(tkr("binance", "BTCUSDT", 1)).unwrap();
+        drop(tx);
+        assert!(rx.recv().await.is_some());
+        drop(tx2);
+        assert!(rx.recv().await.is_some());
+        let end = timeout(Duration::from_secs(1), rx.recv()).await;
+        assert!(matches!(end, Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn senders_see_the_receiver_close() {
+        let (tx, rx) = channel();
+        assert!(!tx.is_closed());
+        let closed = tokio::spawn({
+            let tx = tx.clone();
+            async move { tx.closed().await }
+        });
+        drop(rx);
+        timeout(Duration::from_secs(1), closed)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(tx.is_closed());
+        assert!(tx.send(trade()).is_err());
+        assert!(tx.send_wait(tkr("binance", "BTCUSDT", 1)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_wait_waits_for_room() {
+        let (tx, mut rx) = channel();
+        for _ in 0..TRADE_QUEUE {
+            tx.send_wait(trade()).await.unwrap();
+        }
+        for t in 0..TKR_BACKLOG as i64 {
+            tx.send_wait(tkr("binance", "BTCUSDT", t)).await.unwrap();
+        }
+        let short = Duration::from_millis(50);
+        assert!(timeout(short, tx.send_wait(trade())).await.is_err());
+        assert!(
+            timeout(short, tx.send_wait(tkr("binance", "BTCUSDT", 1000)))
+                .await
+                .is_err()
+        );
+
+        // Taking the tickers and a trade makes room, with nothing dropped
+        let waiting = tokio::spawn({
+            let tx = tx.clone();
+            async move {
+                tx.send_wait(tkr("binance", "BTCUSDT", 1000)).await.unwrap();
+                tx.send_wait(trade()).await.unwrap();
+            }
+        });
+        assert!(matches!(rx.recv().await, Some(WsEvent::Trade(_))));
+        while !matches!(rx.try_recv(), Some(WsEvent::Tkr(_))) {}
+        timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(rx.dropped(), Dropped::default());
+        let mut tail = None;
+        while let Some(event) = rx.try_recv() {
+            tail = Some(event);
+        }
+        assert_eq!(times(tail).1, vec![1000]);
+    }
+}
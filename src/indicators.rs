@@ -0,0 +1,503 @@
+use crate::config::IndicatorConfig;
+use crate::models::Candle;
+use crate::series::{TimeSeries, Timestamped};
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+const DAY: i64 = 86_400_000;
+
+/// Studies drawn on the chart: the first four over the candles, RSI and
+/// MACD each in a pane below
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Study {
+    Sma,
+    Ema,
+    Vwap,
+    Bollinger,
+    Rsi,
+    Macd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bands {
+    pub upper: f64,
+    pub middle: f64,
+    pub lower: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MacdValue {
+    pub macd: f64,
+    pub signal: Option<f64>, // once the signal EMA has enough history
+}
+
+impl MacdValue {
+    pub fn histogram(&self) -> Option<f64> {
+        self.signal.map(|signal| self.macd - signal)
+    }
+}
+
+/// Every study as of one candle; None until it has enough history
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub start: i64, // of the candle
+    pub sma: Option<f64>,
+    pub ema: Option<f64>,
+    pub vwap: Option<f64>,
+    pub bands: Option<Bands>,
+    pub rsi: Option<f64>,
+    pub macd: Option<MacdValue>,
+}
+
+impl Timestamped for Point {
+    fn time(&self) -> i64 {
+        self.start
+    }
+}
+
+/// Simple moving average over a sliding window
+#[derive(Clone)]
+struct Sma {
+    period: usize,
+    window: VecDeque<f64>,
+    sum: f64,
+}
+
+impl Sma {
+    fn new(period: usize) -> Self {
+        Self {
+            period,
+            window: VecDeque::with_capacity(period + 1),
+            sum: 0.0,
+        }
+    }
+
+    fn next(&mut self, value: f64) -> Option<f64> {
+        self.window.push_back(value);
+        self.sum += value;
+        if self.window.len() > self.period {
+            self.sum -= self.window.pop_front().unwrap_or_default();
+        }
+        (self.window.len() == self.period).then(|| self.sum / self.period as f64)
+    }
+}
+
+/// Exponential moving average seeded with the SMA of its first `period` values
+#[derive(Clone)]
+struct Ema {
+    alpha: f64,
+    seed: Sma,
+    value: Option<f64>,
+}
+
+impl Ema {
+    fn new(period: usize) -> Self {
+        Self {
+            alpha: 2.0 / (period as f64 + 1.0),
+            seed: Sma::new(period),
+            value: None,
+        }
+    }
+
+    fn next(&mut self, value: f64) -> Option<f64> {
+        self.value = match self.value {
+            Some(ema) => Some(ema + self.alpha * (value - ema)),
+            None => self.seed.next(value),
+        };
+        self.value
+    }
+}
+
+/// Moving average with bands `k` population standard deviations either side
+#[derive(Clone)]
+struct Bollinger {
+    sma: Sma,
+    k: f64,
+}
+
+impl Bollinger {
+    fn next(&mut self, value: f64) -> Option<Bands> {
+        let middle = self.sma.next(value)?;
+        let variance = self
+            .sma
+            .window
+            .iter()
+            .map(|v| (v - middle).powi(2))
+            .sum::<f64>()
+            / self.sma.period as f64;
+        let width = self.k * variance.sqrt();
+        Some(Bands {
+            upper: middle + width,
+            middle,
+            lower: middle - width,
+        })
+    }
+}
+
+/// Relative strength index with Wilder's smoothing
+#[derive(Clone)]
+struct Rsi {
+    period: usize,
+    prev: Option<f64>,
+    changes: usize,
+    gain: f64,
+    loss: f64,
+}
+
+impl Rsi {
+    fn new(period: usize) -> Self {
+        Self {
+            period,
+            prev: None,
+            changes: 0,
+            gain: 0.0,
+            loss: 0.0,
+        }
+    }
+
+    fn next(&mut self, value: f64) -> Option<f64> {
+        let prev = self.prev.replace(value)?;
+        let change = value - prev;
+        let (gain, loss) = (change.max(0.0), (-change).max(0.0));
+        let n = self.period as f64;
+        self.changes += 1;
+        if self.changes <= self.period {
+            // The first averages are plain means of the first `period` changes
+            self.gain += gain / n;
+            self.loss += loss / n;
+            if self.changes < self.period {
+                return None;
+            }
+        } else {
+            self.gain = (self.gain * (n - 1.0) + gain) / n;
+            self.loss = (self.loss * (n - 1.0) + loss) / n;
+        }
+        if self.loss == 0.0 {
+            return Some(if self.gain == 0.0 { 50.0 } else { 100.0 });
+        }
+        Some(100.0 - 100.0 / (1.0 + self.gain / self.loss))
+    }
+}
+
+#[derive(Clone)]
+struct Macd {
+    fast: Ema,
+    slow: Ema,
+    signal: Ema,
+}
+
+impl Macd {
+    fn next(&mut self, value: f64) -> Option<MacdValue> {
+        let (fast, slow) = (self.fast.next(value), self.slow.next(value));
+        let macd = fast? - slow?;
+        Some(MacdValue {
+            macd,
+            signal: self.signal.next(macd),
+        })
+    }
+}
+
+/// Volume weighted average of the typical price, restarting each UTC day
+#[derive(Clone, Default)]
+struct Vwap {
+    day: i64,
+    price_volume: f64,
+    volume: f64,
+}
+
+impl Vwap {
+    fn next(&mut self, candle: &Candle) -> Option<f64> {
+        let day = candle.start.div_euclid(DAY);
+        if day != self.day {
+            *self = Self {
+                day,
+                ..Self::default()
+            };
+        }
+        let typical = (candle.high + candle.low + candle.close) / 3.0;
+        self.price_volume += typical * candle.volume;
+        self.volume += candle.volume;
+        (self.volume > 0.0).then(|| self.price_volume / self.volume)
+    }
+}
+
+/// Running state of every study, advanced one candle at a time
+#[derive(Clone)]
+struct State {
+    sma: Sma,
+    ema: Ema,
+    vwap: Vwap,
+    bollinger: Bollinger,
+    rsi: Rsi,
+    macd: Macd,
+}
+
+impl State {
+    fn new(config: &IndicatorConfig) -> Self {
+        let [fast, slow, signal] = config.macd;
+        Self {
+            sma: Sma::new(config.sma),
+            ema: Ema::new(config.ema),
+            vwap: Vwap::default(),
+            bollinger: Bollinger {
+                sma: Sma::new(config.bollinger),
+                k: config.bollinger_k,
+            },
+            rsi: Rsi::new(config.rsi),
+            macd: Macd {
+                fast: Ema::new(fast),
+                slow: Ema::new(slow),
+                signal: Ema::new(signal),
+            },
+        }
+    }
+
+    fn next(&mut self, candle: &Candle) -> Point {
+        let close = candle.close;
+        Point {
+            start: candle.start,
+            sma: self.sma.next(close),
+            ema: self.ema.next(close),
+            vwap: self.vwap.next(candle),
+            bands: self.bollinger.next(close),
+            rsi: self.rsi.next(close),
+            macd: self.macd.next(close),
+        }
+    }
+}
+
+/// Studies of one candle series, one point per candle. Closed candles are
+/// folded into the state once; only the candle still forming is evaluated
+/// again on each update, from a copy of that state.
+pub struct Indicators {
+    closed: State,
+    closed_at: Option<i64>, // start of the newest candle folded in
+    pub points: TimeSeries<Point>,
+}
+
+impl Indicators {
+    pub fn new(config: &IndicatorConfig, capacity: usize) -> Self {
+        Self {
+            closed: State::new(config),
+            closed_at: None,
+            points: TimeSeries::new(capacity),
+        }
+    }
+
+    /// Catches up with `candles`; a candle counts as closed once a newer one exists
+    pub fn update(&mut self, candles: &TimeSeries<Candle>) {
+        let Some(newest) = candles.newest() else {
+            return;
+        };
+        let from = self.closed_at.map_or(i64::MIN, |t| t + 1);
+        for candle in candles.range(from..newest.start) {
+            let point = self.closed.next(candle);
+            self.set(point);
+            self.closed_at = Some(candle.start);
+        }
+        if self.closed_at.is_some_and(|t| t >= newest.start) {
+            return;
+        }
+        let point = self.closed.clone().next(newest);
+        self.set(point);
+    }
+
+    fn set(&mut self, point: Point) {
+        match self.points.newest_mut() {
+            Some(newest) if newest.start == point.start => *newest = point,
+            _ => {
+                self.points.push(point);
+            }
+        }
+    }
+
+    pub fn at(&self, start: i64) -> Option<&Point> {
+        self.points.range(start..start + 1).next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // StockCharts' RSI sample closes, and their 10-day SMA/EMA sample closes
+    const RSI_CLOSES: [f64; 33] = [
+        44.34, 44.09, 44.15, 43.61, 44.33, 44.83, 45.10, 45.42, 45.84, 46.08, 45.89, 46.03, 45.61,
+        46.28, 46.28, 46.00, 46.03, 46.41, 46.22, 45.64, 46.21, 46.25, 45.71, 46.45, 45.78, 45.35,
+        44.03, 44.18, 44.22, 44.57, 43.42, 42.66, 43.13,
+    ];
+    const MA_CLOSES: [f64; 30] = [
+        22.27, 22.19, 22.08, 22.17, 22.18, 22.13, 22.23, 22.43, 22.24, 22.29, 22.15, 22.39, 22.38,
+        22.61, 23.36, 24.05, 23.75, 23.83, 23.95, 23.63, 23.82, 23.87, 23.65, 23.19, 23.10, 23.33,
+        22.68, 23.10, 22.40, 22.17,
+    ];
+
+    /// `values` to two decimals, leaving out the warm up
+    fn rounded(values: impl Iterator<Item = Option<f64>>) -> Vec<f64> {
+        values
+            .flatten()
+            .map(|v| (v * 100.0).round() / 100.0)
+            .collect()
+    }
+
+    fn assert_near(found: &[f64], expected: &[f64], tolerance: f64) {
+        assert_eq!(found.len(), expected.len(), "{:?}", found);
+        for (f, e) in found.iter().zip(expected) {
+            assert!((f - e).abs() <= tolerance, "{:?} vs {:?}", found, expected);
+        }
+    }
+
+    #[test]
+    fn rsi_follows_wilder() {
+        let mut rsi = Rsi::new(14);
+        let values: Vec<Option<f64>> = RSI_CLOSES.iter().map(|c| rsi.next(*c)).collect();
+        // The first value needs 14 changes, so 15 closes
+        assert!(values[..14].iter().all(Option::is_none));
+        assert_eq!(
+            rounded(values.into_iter()),
+            [
+                70.46, 66.25, 66.48, 69.35, 66.29, 57.92, 62.88, 63.21, 56.01, 62.34, 54.67, 50.39,
+                40.02, 41.49, 41.90, 45.50, 37.32, 33.09, 37.79,
+            ]
+        );
+        // Neither gains nor losses
+        let mut flat = Rsi::new(3);
+        let values: Vec<Option<f64>> = (0..4).map(|_| flat.next(1.0)).collect();
+        assert_eq!(values, [None, None, None, Some(50.0)]);
+    }
+
+    #[test]
+    fn sma_and_ema_match_published_tables() {
+        let mut sma = Sma::new(10);
+        let mut ema = Ema::new(10);
+        let smas = rounded(MA_CLOSES.iter().map(|c| sma.next(*c)));
+        let emas = rounded(MA_CLOSES.iter().map(|c| ema.next(*c)));
+        // Published to two decimals from rounded intermediate values
+        assert_near(
+            &smas,
+            &[
+                22.22, 22.21, 22.23, 22.26, 22.31, 22.42, 22.61, 22.77, 22.91, 23.08, 23.21, 23.38,
+                23.53, 23.65, 23.71, 23.69, 23.61, 23.51, 23.43, 23.28, 23.13,
+            ],
+            0.011,
+        );
+        assert_near(
+            &emas,
+            &[
+                22.22, 22.21, 22.24, 22.27, 22.33, 22.52, 22.80, 22.97, 23.13, 23.28, 23.34, 23.43,
+                23.51, 23.54, 23.47, 23.40, 23.39, 23.26, 23.23, 23.08, 22.92,
+            ],
+            0.011,
+        );
+    }
+
+    #[test]
+    fn macd_is_the_ema_spread_with_an_ema_signal() {
+        let closes: Vec<f64> = (0..60)
+            .map(|i| 100.0 + 10.0 * (i as f64 / 5.0).sin() + i as f64 * 0.3)
+            .collect();
+        let mut macd = Macd {
+            fast: Ema::new(12),
+            slow: Ema::new(26),
+            signal: Ema::new(9),
+        };
+        let values: Vec<Option<MacdValue>> = closes.iter().map(|c| macd.next(*c)).collect();
+        assert!(values[..25].iter().all(Option::is_none));
+        // Worked out separately from the definitions
+        for (i, line, signal) in [
+            (25, -4.697797, None),
+            (33, -0.163064, Some(-2.80052)),
+            (45, 3.715216, Some(3.320869)),
+            (59, -1.149126, Some(-0.630964)),
+        ] {
+            let value = values[i].unwrap();
+            assert!((value.macd - line).abs() < 1e-6, "{} {:?}", i, value);
+            match (value.signal, signal) {
+                (Some(found), Some(expected)) => assert!((found - expected).abs() < 1e-6),
+                (found, expected) => assert_eq!(found, expected),
+            }
+            assert_eq!(value.histogram(), value.signal.map(|s| value.macd - s));
+        }
+    }
+
+    #[test]
+    fn bollinger_uses_the_population_deviation() {
+        let mut bollinger = Bollinger {
+            sma: Sma::new(5),
+            k: 2.0,
+        };
+        let bands: Vec<Option<Bands>> = (1..=6).map(|v| bollinger.next(v as f64)).collect();
+        assert!(bands[..4].iter().all(Option::is_none));
+        let width = 2.0 * 2f64.sqrt(); // values 1 to 5: mean 3, variance 2
+        assert_eq!(
+            bands[4],
+            Some(Bands {
+                upper: 3.0 + width,
+                middle: 3.0,
+                lower: 3.0 - width,
+            })
+        );
+        assert_eq!(bands[5].unwrap().middle, 4.0);
+    }
+
+    fn candle(start: i64, close: f64, volume: f64) -> Candle {
+        Candle {
+            start,
+            open: close,
+            high: close + 3.0,
+            low: close,
+            close,
+            volume,
+        }
+    }
+
+    #[test]
+    fn vwap_weights_the_typical_price_and_restarts_daily() {
+        let mut vwap = Vwap::default();
+        // Typical prices (h + l + c) / 3 of 11, 21 and 31
+        assert_eq!(vwap.next(&candle(0, 10.0, 1.0)), Some(11.0));
+        assert_eq!(vwap.next(&candle(60_000, 20.0, 3.0)), Some(18.5));
+        assert_eq!(vwap.next(&candle(DAY, 30.0, 2.0)), Some(31.0));
+        assert_eq!(vwap.next(&candle(DAY + 60_000, 40.0, 0.0)), Some(31.0));
+    }
+
+    /// Points of a fresh Indicators given `candles` in one go
+    fn recomputed(config: &IndicatorConfig, candles: &TimeSeries<Candle>) -> Vec<Point> {
+        let mut indicators = Indicators::new(config, 100);
+        indicators.update(candles);
+        indicators.points.iter().copied().collect()
+    }
+
+    #[test]
+    fn incremental_updates_match_a_recompute() {
+        let config = IndicatorConfig {
+            sma: 5,
+            ema: 5,
+            bollinger: 5,
+            rsi: 5,
+            macd: [3, 6, 3],
+            ..IndicatorConfig::default()
+        };
+        let mut candles = TimeSeries::new(100);
+        let mut indicators = Indicators::new(&config, 100);
+        for (i, close) in RSI_CLOSES.iter().enumerate() {
+            let start = i as i64 * 3_600_000;
+            // The forming candle changes a few times before the next opens
+            for tick in 0..3 {
+                let forming = candle(start, close + tick as f64 * 0.1, 1.0 + tick as f64);
+                match candles.get_mut(start) {
+                    Some(existing) => *existing = forming,
+                    None => {
+                        candles.push(forming);
+                    }
+                }
+                indicators.update(&candles);
+                let points: Vec<Point> = indicators.points.iter().copied().collect();
+                assert_eq!(points, recomputed(&config, &candles), "candle {}", i);
+            }
+        }
+        let newest = indicators.at(32 * 3_600_000).unwrap();
+        assert!(newest.sma.is_some() && newest.rsi.is_some() && newest.macd.is_some());
+    }
+}
@@ -1,15 +1,11 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use clap::Parser;
 use cli_log::*;
-use ratatui::{
-    backend::CrosstermBackend,
-    crossterm::{
-        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-        ExecutableCommand,
-    },
-    Terminal,
-};
+use ratatui::{backend::CrosstermBackend, Terminal};
 use std::env;
-use std::io::stdout;
+use std::io::{stdout, Write};
+use std::path::Path;
 use tokio::sync::mpsc::{self, Receiver, Sender};
 
 mod tui;
@@ -17,51 +13,349 @@ use tui::Tui;
 mod ws_client;
 use ws_client::WsClient;
 mod models;
-use models::TkrResult;
+use models::{SubAck, WsCommand, WsEvent};
+mod alerts;
+use alerts::Alerts;
+mod catalogue;
+mod delivery;
+use delivery::Hooks;
+mod feed;
+mod indicators;
+use feed::{FeedRx, FeedTx};
+mod cli;
+use cli::{Cli, Command};
+mod config;
+mod crash;
+use config::Config;
+mod portfolio;
+use portfolio::Positions;
+mod recording;
+mod series;
+use recording::{Frames, Recorder, Replay, Speed};
+mod sources;
+use sources::{
+    binance::{self, Binance},
+    cryptocom::CryptoCom,
+    Fanout, MarketDataSource, Protocol,
+};
 mod components;
 
-const WS_URL: &str = "wss://stream.crypto.com/exchange/v1/market";
-
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    if let Some(level) = &cli.global.log_level {
+        // cli-log reads its level from CRYPTO_TUI_LOG
+        env::set_var("CRYPTO_TUI_LOG", level);
+    }
     init_cli_log!();
-    stdout().execute(EnterAlternateScreen)?;
-    enable_raw_mode()?;
+    crash::install_panic_hook();
+
+    // Report config errors before the terminal enters raw mode
+    let mut config = Config::load(cli.global.config.as_deref())?;
+    cli.global.apply(&mut config)?;
+
+    let (tx, rx) = feed::channel();
+    let (cmd_tx, cmd_rx): (Sender<WsCommand>, Receiver<WsCommand>) = mpsc::channel(16);
+
+    match cli.into_command() {
+        Command::Tui {
+            instruments,
+            record,
+        } => {
+            let watchlist = cli::watchlist(instruments, &config);
+            let recorder = record.as_deref().map(Recorder::create).transpose()?;
+            let source = live_source(&config, tx.clone(), cmd_rx, watchlist.clone(), recorder);
+            let hooks = Hooks::new(&config.alerts, tx.clone())?;
+            spawn_catalogue(&config, tx);
+            let alerts = load_alerts(&config)?;
+            run_tui(config, watchlist, source, alerts, hooks, rx, cmd_tx).await
+        }
+        Command::Record { out, instruments } => {
+            let watchlist = cli::watchlist(instruments, &config);
+            let recorder = Recorder::create(&out)?;
+            let source = live_source(&config, tx, cmd_rx, watchlist, Some(recorder));
+            run_record(source, rx, &out).await
+        }
+        Command::Replay {
+            file,
+            speed,
+            step,
+            instruments,
+        } => {
+            let contents = recording::scan(&file)?;
+            if contents.venues.is_empty() {
+                return Err(anyhow!("no market data in {}", file.display()));
+            }
+            // The first venue recorded plays the selected one
+            config.endpoint.venue = contents.venues[0].clone();
+            config.endpoint.compare = contents.venues[1..].to_vec();
+            let watchlist = if instruments.is_empty() {
+                contents.instruments
+            } else {
+                instruments
+            };
+            let speed = if step {
+                Speed::Step
+            } else {
+                Speed::Rate(speed)
+            };
+            let replay = Replay::new(
+                Frames::open(&file)?,
+                tx.clone(),
+                cmd_rx,
+                watchlist.clone(),
+                speed,
+            );
+            spawn_catalogue(&config, tx);
+            // Recorded prices would otherwise leave live alerts fired, and
+            // aren't news for the hooks
+            let alerts = load_alerts(&config)?.without_saving();
+            run_tui(
+                config,
+                watchlist,
+                Box::new(replay),
+                alerts,
+                None,
+                rx,
+                cmd_tx,
+            )
+            .await
+        }
+        Command::Stream { instruments } => {
+            let watchlist = cli::watchlist(instruments, &config);
+            let source = live_source(&config, tx, cmd_rx, watchlist, None);
+            // cmd_tx is kept so the client runs until the output is closed
+            run_stream(source, rx, cmd_tx).await
+        }
+        Command::ListInstruments { query, limit } => list_instruments(config, query, limit).await,
+    }
+}
+
+/// Websocket clients for the selected venue and any comparison venues
+fn live_source(
+    config: &Config,
+    tx: FeedTx,
+    cmd_rx: Receiver<WsCommand>,
+    watchlist: Vec<String>,
+    recorder: Option<Recorder>,
+) -> Box<dyn MarketDataSource> {
+    let venues = config.endpoint.venues();
+    if venues.len() == 1 {
+        return venue_source(config, &venues[0], tx, cmd_rx, watchlist, recorder);
+    }
+    let mut fanout = Fanout::new(cmd_rx);
+    let mut recorder = recorder; // only the selected venue is recorded
+    for venue in venues {
+        let (venue_cmd_tx, venue_cmd_rx) = mpsc::channel(16);
+        let source = venue_source(
+            config,
+            &venue,
+            tx.clone(),
+            venue_cmd_rx,
+            watchlist.clone(),
+            recorder.take(),
+        );
+        fanout.push(source, venue_cmd_tx);
+    }
+    Box::new(fanout)
+}
+
+fn venue_source(
+    config: &Config,
+    venue: &str,
+    tx: FeedTx,
+    cmd_rx: Receiver<WsCommand>,
+    watchlist: Vec<String>,
+    recorder: Option<Recorder>,
+) -> Box<dyn MarketDataSource> {
+    let url = config.endpoint.url(venue);
+    match venue {
+        binance::VENUE => {
+            let ws_client = WsClient::new(url, Binance::new(), tx, cmd_rx, watchlist);
+            with_recorder(ws_client, recorder)
+        }
+        _ => with_recorder(
+            WsClient::new(url, CryptoCom::new(), tx, cmd_rx, watchlist),
+            recorder,
+        ),
+    }
+}
+
+fn with_recorder<P: Protocol + 'static>(
+    ws_client: WsClient<P>,
+    recorder: Option<Recorder>,
+) -> Box<dyn MarketDataSource> {
+    match recorder {
+        Some(recorder) => Box::new(ws_client.record(recorder)),
+        None => Box::new(ws_client),
+    }
+}
+
+/// Loads the instrument catalogue in the background for the add prompt
+fn spawn_catalogue(config: &Config, tx: FeedTx) {
+    let instruments_url = config.endpoint.instruments_url.clone();
+    crash::spawn("catalogue", async move {
+        match catalogue::load(&instruments_url).await {
+            Ok(instruments) => {
+                let _ = tx.send(WsEvent::Catalogue(instruments));
+            }
+            Err(e) => debug!("Instrument catalogue unavailable: {}", e),
+        }
+    });
+}
+
+fn load_alerts(config: &Config) -> Result<Alerts> {
+    let now = Utc::now().timestamp_millis();
+    Alerts::load(Alerts::default_path(), &config.alerts.rules, now)
+}
+
+async fn run_tui(
+    config: Config,
+    watchlist: Vec<String>,
+    mut source: Box<dyn MarketDataSource>,
+    alerts: Alerts,
+    hooks: Option<Hooks>,
+    rx: FeedRx,
+    cmd_tx: Sender<WsCommand>,
+) -> Result<()> {
+    let positions = Positions::load(Positions::default_path())?;
+    // Connect first so errors are reported on a normal terminal
+    source.connect().await?;
+
+    crash::enter_terminal()?;
     let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
     terminal.clear()?;
 
-    let (tx, rx): (Sender<TkrResult>, Receiver<TkrResult>) = mpsc::channel(5);
+    let tui = Tui::new(rx, cmd_tx, watchlist, config, alerts, hooks, positions);
 
-    // Get watchlist from args
-    let mut watchlist = vec!["BTCUSD-PERP", "ETHUSD-PERP"];
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
-        watchlist = args[1..].iter().map(|tkr| tkr.as_str()).collect();
+    // Run concurrent; a signal drops both mid-flight
+    tokio::select! {
+        result = async { tokio::try_join!(tui.run(terminal), source.run()) } => {
+            if let Err(e) = result {
+                debug!("Tasks interrupted: {}", e);
+            }
+        }
+        signal = shutdown_signal() => debug!("Received {}, shutting down", signal),
     }
+    crash::restore_terminal();
+    let closed = source.close();
+    logger().flush();
+    closed
+}
 
-    let mut ws_client = match WsClient::new(WS_URL, tx, watchlist.clone()).connect().await {
-        Ok(ws) => ws,
-        Err(e) => {
-            debug!("WS Error: {}", e);
-            // clean up terminal on websocket connection error
-            stdout().execute(LeaveAlternateScreen)?;
-            disable_raw_mode()?;
-
-            return Err(e);
+/// The first of SIGINT, SIGTERM or SIGHUP
+#[cfg(unix)]
+async fn shutdown_signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+    let wait = |kind: SignalKind| async move {
+        match signal(kind) {
+            Ok(mut stream) => stream.recv().await,
+            Err(_) => std::future::pending().await,
         }
     };
+    tokio::select! {
+        _ = wait(SignalKind::interrupt()) => "SIGINT",
+        _ = wait(SignalKind::terminate()) => "SIGTERM",
+        _ = wait(SignalKind::hangup()) => "SIGHUP",
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "Ctrl-C"
+}
+
+/// Headless recording of the raw feed until Ctrl-C or another signal
+async fn run_record(
+    mut source: Box<dyn MarketDataSource>,
+    mut rx: FeedRx,
+    out: &Path,
+) -> Result<()> {
+    source.connect().await?;
+
+    eprintln!("Recording to {}, press Ctrl-C to stop", out.display());
+    // Decoded events aren't needed, the recorder keeps the raw frames
+    let drain = async { while rx.recv().await.is_some() {} };
+    tokio::select! {
+        result = source.run() => result?,
+        _ = drain => {}
+        _ = shutdown_signal() => {}
+    }
+    source.close()?;
+    eprintln!("Saved {}", out.display());
+    Ok(())
+}
 
-    let tui = Tui::new(rx, &watchlist);
+/// Headless mode: one JSON object per market data message on stdout
+async fn run_stream(
+    mut source: Box<dyn MarketDataSource>,
+    mut rx: FeedRx,
+    _cmd_tx: Sender<WsCommand>,
+) -> Result<()> {
+    source.connect().await?;
 
-    // Run concurrent
-    let result = tokio::try_join!(tui.run(terminal), ws_client.run());
-    match result {
-        Ok((_tui, _ws)) => {}
-        Err(e) => debug!("Tasks interrupted: {}", e),
+    let printer = async move {
+        let mut out = stdout().lock();
+        while let Some(event) = rx.recv().await {
+            let line = match event {
+                WsEvent::Tkr(result) => serde_json::to_string(&result)?,
+                WsEvent::Book(result) => serde_json::to_string(&result)?,
+                WsEvent::Trade(result) => serde_json::to_string(&result)?,
+                WsEvent::Status(status) => {
+                    debug!("Link status: {:?}", status.state);
+                    continue;
+                }
+                WsEvent::SubAck(SubAck {
+                    tkr,
+                    error: Some(error),
+                    ..
+                }) => {
+                    eprintln!("{}: {}", tkr, error);
+                    continue;
+                }
+                WsEvent::SubAck(_) | WsEvent::Catalogue(_) | WsEvent::Delivery(_) => continue,
+            };
+            // Stop quietly when the reader goes away, e.g. `| head`
+            if writeln!(out, "{}", line).and_then(|_| out.flush()).is_err() {
+                break;
+            }
+        }
+        Ok::<(), anyhow::Error>(())
+    };
+
+    tokio::select! {
+        result = async { tokio::try_join!(printer, source.run()) } => { result?; }
+        _ = shutdown_signal() => {}
     }
+    source.close()
+}
 
-    stdout().execute(LeaveAlternateScreen)?;
-    disable_raw_mode()?;
+async fn list_instruments(config: Config, query: Option<String>, limit: usize) -> Result<()> {
+    let instruments = catalogue::load(&config.endpoint.instruments_url).await?;
+    let matches = match &query {
+        Some(query) => catalogue::search(&instruments, query, limit),
+        None => instruments.iter().take(limit).collect(),
+    };
 
+    let mut out = stdout().lock();
+    writeln!(
+        out,
+        "{:<24} {:<16} {:<8} {:<8} {:>12} {:>12} TRADABLE",
+        "SYMBOL", "TYPE", "BASE", "QUOTE", "PRICE TICK", "QTY TICK"
+    )?;
+    for inst in matches {
+        writeln!(
+            out,
+            "{:<24} {:<16} {:<8} {:<8} {:>12} {:>12} {}",
+            inst.symbol,
+            inst.inst_type,
+            inst.base_ccy,
+            inst.quote_ccy,
+            inst.price_tick_size,
+            inst.qty_tick_size,
+            inst.tradable
+        )?;
+    }
     Ok(())
 }
@@ -1,72 +1,852 @@
-// TODO
-// - parse TkrData out of String
-
-use serde::{Deserialize, Serialize};
+use crate::delivery::Delivery;
+use crate::series::{TimeSeries, Timestamped};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
+/// crypto.com ticker; Binance tickers are mapped onto it. Prices are exact,
+/// fields the exchange may send as null are `None` rather than zero.
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct TkrData {
-    pub h: String,  // Price of the 24h highest trade
-    pub l: String,  // Price of the 24h lowest trade, null if there weren't any trades
-    pub a: String,  // The price of the latest trade, null if there weren't any trades
-    pub i: String,  // Instrument name
-    pub v: String,  // The total 24h traded volume
-    pub vv: String, // The total 24h traded volume value (in USD)
-    pub oi: String, // Open interest
-    pub c: String,  // 24-hour price change, null if there weren't any trades
-    pub b: String,  // The current best bid price, null if there aren't any bids
-    pub k: String,  // The current best ask price, null if there aren't any asks
+    #[serde(rename = "h", default, deserialize_with = "decimal_opt")]
+    pub high: Option<Decimal>, // Price of the 24h highest trade
+    #[serde(rename = "l", default, deserialize_with = "decimal_opt")]
+    pub low: Option<Decimal>, // Price of the 24h lowest trade, null if there weren't any trades
+    #[serde(rename = "a", default, deserialize_with = "decimal_opt")]
+    pub last: Option<Decimal>, // The price of the latest trade, null if there weren't any trades
+    #[serde(rename = "i")]
+    pub instrument: String,
+    #[serde(rename = "v", default, deserialize_with = "decimal_opt")]
+    pub volume: Option<Decimal>, // The total 24h traded volume
+    #[serde(rename = "vv", default, deserialize_with = "decimal_opt")]
+    pub volume_usd: Option<Decimal>, // The total 24h traded volume value (in USD)
+    #[serde(rename = "oi", default, deserialize_with = "decimal_opt")]
+    pub open_interest: Option<Decimal>, // derivatives only
+    #[serde(rename = "c", default, deserialize_with = "decimal_opt")]
+    pub change: Option<Decimal>, // 24-hour price change as a fraction, null if there weren't any trades
+    #[serde(rename = "b", default, deserialize_with = "decimal_opt")]
+    pub bid: Option<Decimal>, // The current best bid price, null if there aren't any bids
+    #[serde(rename = "k", default, deserialize_with = "decimal_opt")]
+    pub ask: Option<Decimal>, // The current best ask price, null if there aren't any asks
+    pub t: i64,
+}
+
+impl Timestamped for TkrData {
+    fn time(&self) -> i64 {
+        self.t
+    }
+}
+
+impl TkrData {
+    /// Midpoint of the best bid and ask, else the last trade price
+    pub fn mid(&self) -> Option<Decimal> {
+        match (self.bid, self.ask) {
+            (Some(bid), Some(ask)) => Some((bid + ask) / Decimal::TWO),
+            _ => self.last,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Trade {
+    #[serde(rename = "d")]
+    pub id: String,
+    #[serde(rename = "t")]
+    pub timestamp: i64,
+    #[serde(rename = "p", deserialize_with = "f64_from_str")]
+    pub price: f64,
+    #[serde(rename = "q", deserialize_with = "f64_from_str")]
+    pub qty: f64,
+    #[serde(rename = "s")]
+    pub side: Side, // taker side
+}
+
+impl Timestamped for Trade {
+    fn time(&self) -> i64 {
+        self.timestamp
+    }
+}
+
+fn f64_from_str<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse::<f64>().map_err(serde::de::Error::custom)
+}
+
+/// Decimal sent as a string or a JSON number; null and "" are `None`
+fn decimal_opt<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Decimal>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Text(String),
+        Number(Decimal),
+    }
+    match Option::<Raw>::deserialize(deserializer)? {
+        Some(Raw::Text(s)) if s.is_empty() => Ok(None),
+        Some(Raw::Text(s)) => parse_decimal(&s)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        Some(Raw::Number(n)) => Ok(Some(n)),
+        None => Ok(None),
+    }
+}
+
+/// Exact decimal from a price string, plain or in scientific notation
+pub fn parse_decimal(s: &str) -> anyhow::Result<Decimal> {
+    s.parse::<Decimal>()
+        .or_else(|_| Decimal::from_scientific(s))
+        .map_err(|_| anyhow::anyhow!("invalid decimal {:?}", s))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BookLevel(pub String, pub String, pub String); // price, qty, number of orders
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BookDelta {
+    #[serde(default)]
+    pub asks: Vec<BookLevel>,
+    #[serde(default)]
+    pub bids: Vec<BookLevel>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BookData {
+    #[serde(default)]
+    pub asks: Vec<BookLevel>, // snapshot levels, empty on book.update
+    #[serde(default)]
+    pub bids: Vec<BookLevel>,
+    pub update: Option<BookDelta>, // only on book.update; qty "0" removes the level
     pub t: i64,
+    pub u: u64,          // update sequence number
+    pub pu: Option<u64>, // previous update sequence number, book.update only
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct TkrResult {
+pub struct ChannelResult<T> {
+    #[serde(default)]
+    pub venue: String, // filled in by the venue's protocol
     #[serde(rename = "instrument_name")]
     pub tkr: String,
     subscription: String,
-    channel: String,
-    pub data: Vec<TkrData>,
+    pub channel: String,
+    pub data: Vec<T>,
+}
+
+impl<T> ChannelResult<T> {
+    /// For venues that don't speak crypto.com's format
+    pub fn new(venue: &str, tkr: &str, channel: &str, data: Vec<T>) -> Self {
+        Self {
+            venue: venue.to_string(),
+            tkr: tkr.to_string(),
+            subscription: format!("{}.{}", channel, tkr),
+            channel: channel.to_string(),
+            data,
+        }
+    }
 }
 
+pub type TkrResult = ChannelResult<TkrData>;
+pub type BookResult = ChannelResult<BookData>;
+pub type TradeResult = ChannelResult<Trade>;
+
 #[derive(Serialize, Deserialize, Debug)]
-pub struct TkrResponse {
+pub struct Response<T> {
     id: i64,
     method: String,
     code: i64,
-    pub result: TkrResult,
+    pub result: ChannelResult<T>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LinkState {
+    #[default]
+    Connected,
+    Reconnecting(u32), // attempt number
+    Disconnected,
+    Replaying,
+    ReplayEnded,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConnStatus {
+    pub venue: String, // empty when not from a single venue, e.g. a replay
+    pub state: LinkState,
+    pub last_msg: HashMap<String, Instant>, // last update received per tkr
+    pub rtt: Option<Duration>,              // ping/pong round trip
+    pub msg_rate: f64,                      // messages per second
+    pub reconnects: u32,
+}
+
+/// A venue's answer to a subscribe or unsubscribe request
+#[derive(Debug, Clone)]
+pub struct SubAck {
+    pub venue: String,
+    pub tkr: String,
+    pub subscribe: bool,       // false when answering an unsubscribe
+    pub error: Option<String>, // readable reason when refused
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Instrument {
+    pub symbol: String,
+    pub inst_type: String, // CCY_PAIR, PERPETUAL_SWAP, FUTURE
+    #[serde(default)]
+    pub display_name: String,
+    pub base_ccy: String,
+    pub quote_ccy: String,
+    pub price_tick_size: String,
+    pub qty_tick_size: String,
+    pub tradable: bool,
+}
+
+/// Control commands from the Tui to the ws_client
+#[derive(Debug, Clone)]
+pub enum WsCommand {
+    Subscribe(String),
+    Unsubscribe(String),
+    ResyncBook(String, String), // venue, tkr: a fresh book snapshot after a gap
+    Step,                       // release the next frame of a step-by-step replay
+}
+
+/// Everything the ws_client hands over to the Tui
+#[derive(Debug)]
+pub enum WsEvent {
+    Tkr(TkrResult),
+    Book(BookResult),
+    Trade(TradeResult),
+    Status(ConnStatus),
+    SubAck(SubAck),
+    Catalogue(Vec<Instrument>),
+    Delivery(Delivery), // of a fired alert to a hook
+}
+
+/// Ticker history is kept per venue and instrument
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MarketKey {
+    pub venue: String,
+    pub tkr: String,
+}
+
+impl MarketKey {
+    pub fn new(venue: &str, tkr: &str) -> Self {
+        Self {
+            venue: venue.to_string(),
+            tkr: tkr.to_string(),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct DataList {
-    pub capacity: usize,
-    insert_i: usize,
-    pub curr_i: usize,
-    pub data: Vec<TkrData>,
+    pub data: TimeSeries<TkrData>,
+    pub malformed: u64, // updates without data or timestamps
+    pub rejected: u64,  // updates older than the latest held
 }
 
 impl DataList {
     pub fn new(n: usize) -> Self {
         Self {
-            capacity: n,
-            insert_i: 0,
-            curr_i: 0,
-            data: vec![TkrData::default(); n],
+            data: TimeSeries::new(n),
+            malformed: 0,
+            rejected: 0,
+        }
+    }
+
+    /// Appends every ticker of an update in time order and returns how many
+    /// were kept. Repeats of the latest timestamp are dropped, older ones
+    /// counted as rejected and entries without a timestamp as malformed.
+    pub fn insert(&mut self, tkr_result: &TkrResult) -> usize {
+        if tkr_result.data.is_empty() {
+            self.malformed += 1;
+            return 0;
+        }
+        let mut incoming: Vec<&TkrData> = tkr_result.data.iter().collect();
+        incoming.sort_by_key(|data| data.t);
+
+        let mut accepted = 0;
+        for data in incoming {
+            if data.t <= 0 {
+                self.malformed += 1;
+                continue;
+            }
+            match self.latest().map(|newest| newest.t) {
+                Some(t) if data.t == t => continue, // resubscribe snapshot
+                Some(t) if data.t < t => {
+                    self.rejected += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            self.data.push(data.clone());
+            accepted += 1;
+        }
+        accepted
+    }
+
+    /// Latest ticker, if any has arrived
+    pub fn latest(&self) -> Option<&TkrData> {
+        self.data.newest()
+    }
+}
+
+/// Local copy of the order book kept in sync from book snapshots and deltas
+#[derive(Clone, Default)]
+pub struct OrderBook {
+    pub bids: Vec<(f64, f64)>, // (price, qty), best bid first
+    pub asks: Vec<(f64, f64)>, // (price, qty), best ask first
+    pub t: i64,
+    last_u: u64,
+    pub synced: bool,   // false until a snapshot arrives or after a sequence gap
+    pub malformed: u64, // levels skipped for a price or qty that doesn't parse
+}
+
+impl OrderBook {
+    /// True when a sequence gap just left the book waiting for a snapshot,
+    /// which the venue has to be asked for
+    pub fn insert(&mut self, book_result: &BookResult) -> bool {
+        let was_synced = self.synced;
+        for data in book_result.data.iter() {
+            match &data.update {
+                Some(delta) => self.apply_delta(data, delta),
+                None => self.apply_snapshot(data),
+            }
+        }
+        was_synced && !self.synced
+    }
+
+    fn apply_snapshot(&mut self, data: &BookData) {
+        self.bids = self.parse_levels(&data.bids);
+        self.asks = self.parse_levels(&data.asks);
+        self.bids.sort_by(|a, b| b.0.total_cmp(&a.0));
+        self.asks.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.t = data.t;
+        self.last_u = data.u;
+        self.synced = true;
+    }
+
+    fn apply_delta(&mut self, data: &BookData, delta: &BookDelta) {
+        if !self.synced {
+            return;
+        }
+        // Deltas must chain onto the last applied update, otherwise wait for
+        // the snapshot a book resubscribe brings
+        if data.pu != Some(self.last_u) {
+            self.synced = false;
+            return;
+        }
+        for level in self.parse_levels(&delta.bids) {
+            update_side(&mut self.bids, level, |a, b| b.total_cmp(&a));
+        }
+        for level in self.parse_levels(&delta.asks) {
+            update_side(&mut self.asks, level, |a, b| a.total_cmp(&b));
+        }
+        self.t = data.t;
+        self.last_u = data.u;
+    }
+
+    /// Levels that parse, counting the others as malformed
+    fn parse_levels(&mut self, levels: &[BookLevel]) -> Vec<(f64, f64)> {
+        let parsed: Vec<(f64, f64)> = levels.iter().filter_map(parse_level).collect();
+        self.malformed += (levels.len() - parsed.len()) as u64;
+        parsed
+    }
+
+    pub fn spread(&self) -> Option<f64> {
+        match (self.bids.first(), self.asks.first()) {
+            (Some((bid, _)), Some((ask, _))) => Some(ask - bid),
+            _ => None,
+        }
+    }
+}
+
+/// (price, qty) of a level; None unless the price is positive and the qty
+/// zero or more, so a bad level can't become the best price or delete one
+fn parse_level(level: &BookLevel) -> Option<(f64, f64)> {
+    let price = level.0.parse::<f64>().ok()?;
+    let qty = level.1.parse::<f64>().ok()?;
+    (price.is_finite() && price > 0.0 && qty.is_finite() && qty >= 0.0).then_some((price, qty))
+}
+
+fn update_side(
+    side: &mut Vec<(f64, f64)>,
+    (price, qty): (f64, f64),
+    cmp: impl Fn(f64, f64) -> std::cmp::Ordering,
+) {
+    match side.binary_search_by(|(p, _)| cmp(*p, price)) {
+        Ok(i) if qty == 0.0 => {
+            side.remove(i);
+        }
+        Ok(i) => side[i].1 = qty,
+        Err(_) if qty == 0.0 => {}
+        Err(i) => side.insert(i, (price, qty)),
+    }
+}
+
+/// Most recent trades, oldest first
+#[derive(Clone)]
+pub struct TradeList {
+    pub trades: TimeSeries<Trade>,
+}
+
+impl TradeList {
+    pub fn new(n: usize) -> Self {
+        Self {
+            trades: TimeSeries::new(n),
+        }
+    }
+
+    /// Appends new trades in time order and returns the ones accepted
+    pub fn insert(&mut self, trade_result: &TradeResult) -> Vec<Trade> {
+        // The subscription snapshot arrives newest first
+        let mut incoming: Vec<&Trade> = trade_result.data.iter().collect();
+        incoming.sort_by_key(|trade| trade.timestamp);
+
+        let mut accepted = Vec::with_capacity(incoming.len());
+        for trade in incoming {
+            // Resubscribing replays recent trades; skip ones already held
+            let seen = self
+                .trades
+                .iter_newest()
+                .take_while(|t| t.timestamp == trade.timestamp)
+                .any(|t| t.id == trade.id);
+            if !seen && self.trades.push(trade.clone()) {
+                accepted.push(trade.clone());
+            }
+        }
+        accepted
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Interval {
+    S1,
+    #[default]
+    S5,
+    M1,
+    M5,
+    M15,
+    H1,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 6] = [
+        Interval::S1,
+        Interval::S5,
+        Interval::M1,
+        Interval::M5,
+        Interval::M15,
+        Interval::H1,
+    ];
+
+    pub fn millis(self) -> i64 {
+        match self {
+            Interval::S1 => 1_000,
+            Interval::S5 => 5_000,
+            Interval::M1 => 60_000,
+            Interval::M5 => 300_000,
+            Interval::M15 => 900_000,
+            Interval::H1 => 3_600_000,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Interval::S1 => "1s",
+            Interval::S5 => "5s",
+            Interval::M1 => "1m",
+            Interval::M5 => "5m",
+            Interval::M15 => "15m",
+            Interval::H1 => "1h",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|i| *i == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    pub fn prev(self) -> Self {
+        let i = Self::ALL.iter().position(|i| *i == self).unwrap_or(0);
+        Self::ALL[(i + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candle {
+    pub start: i64, // bucket start, unix millis
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+impl Timestamped for Candle {
+    fn time(&self) -> i64 {
+        self.start
+    }
+}
+
+impl Candle {
+    fn new(start: i64, price: f64, qty: f64) -> Self {
+        Self {
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: qty,
+        }
+    }
+
+    fn update(&mut self, price: f64, qty: f64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += qty;
+    }
+}
+
+/// OHLCV candles of one interval built from trades, oldest first
+#[derive(Clone)]
+pub struct CandleSeries {
+    pub interval: Interval,
+    pub candles: TimeSeries<Candle>,
+}
+
+impl CandleSeries {
+    pub fn new(interval: Interval, capacity: usize) -> Self {
+        Self {
+            interval,
+            candles: TimeSeries::new(capacity),
+        }
+    }
+
+    pub fn update(&mut self, timestamp: i64, price: f64, qty: f64) {
+        let start = timestamp - timestamp.rem_euclid(self.interval.millis());
+        match self.candles.newest_mut() {
+            Some(candle) if candle.start == start => candle.update(price, qty),
+            Some(candle) if candle.start > start => {
+                // late trade for an older bucket, dropped if that isn't held
+                if let Some(candle) = self.candles.get_mut(start) {
+                    candle.update(price, qty);
+                }
+            }
+            _ => {
+                self.candles.push(Candle::new(start, price, qty));
+            }
+        }
+    }
+}
+
+/// Candle series for every interval of one instrument
+#[derive(Clone)]
+pub struct Candles {
+    series: Vec<CandleSeries>,
+}
+
+impl Candles {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            series: Interval::ALL
+                .iter()
+                .map(|interval| CandleSeries::new(*interval, capacity))
+                .collect(),
+        }
+    }
+
+    pub fn insert(&mut self, trades: &[Trade]) {
+        for trade in trades {
+            for series in self.series.iter_mut() {
+                series.update(trade.timestamp, trade.price, trade.qty);
+            }
+        }
+    }
+
+    pub fn get(&self, interval: Interval) -> &CandleSeries {
+        &self.series[Interval::ALL
+            .iter()
+            .position(|i| *i == interval)
+            .unwrap_or(0)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: &str, qty: &str) -> BookLevel {
+        BookLevel(price.to_string(), qty.to_string(), "1".to_string())
+    }
+
+    fn book(data: BookData) -> BookResult {
+        ChannelResult::new("crypto.com", "BTCUSD-PERP", "book", vec![data])
+    }
+
+    #[test]
+    fn skips_malformed_book_levels() {
+        let mut order_book = OrderBook::default();
+        order_book.insert(&book(BookData {
+            asks: vec![
+                level("abc", "1"),
+                level("101", "2"),
+                level("-5", "1"),
+                level("102", "NaN"),
+            ],
+            bids: vec![level("100", "1"), level("99", "")],
+            update: None,
+            t: 1,
+            u: 1,
+            pu: None,
+        }));
+        assert_eq!(order_book.asks, [(101.0, 2.0)]);
+        assert_eq!(order_book.bids, [(100.0, 1.0)]);
+        assert_eq!(order_book.spread(), Some(1.0));
+        assert_eq!(order_book.malformed, 4);
+
+        // A bad qty in a delta leaves the level alone rather than deleting it
+        order_book.insert(&book(BookData {
+            asks: Vec::new(),
+            bids: Vec::new(),
+            update: Some(BookDelta {
+                asks: vec![level("101", "oops"), level("103", "4")],
+                bids: vec![level("100", "0")],
+            }),
+            t: 2,
+            u: 2,
+            pu: Some(1),
+        }));
+        assert_eq!(order_book.asks, [(101.0, 2.0), (103.0, 4.0)]);
+        assert!(order_book.bids.is_empty());
+        assert_eq!(order_book.malformed, 5);
+    }
+
+    fn snapshot(u: u64, bid: &str, ask: &str) -> BookResult {
+        book(BookData {
+            asks: vec![level(ask, "1")],
+            bids: vec![level(bid, "1")],
+            update: None,
+            t: u as i64,
+            u,
+            pu: None,
+        })
+    }
+
+    fn delta(u: u64, pu: u64, bid: &str) -> BookResult {
+        book(BookData {
+            asks: Vec::new(),
+            bids: Vec::new(),
+            update: Some(BookDelta {
+                asks: Vec::new(),
+                bids: vec![level(bid, "2")],
+            }),
+            t: u as i64,
+            u,
+            pu: Some(pu),
+        })
+    }
+
+    #[test]
+    fn resyncs_the_book_after_a_gap() {
+        let mut order_book = OrderBook::default();
+        assert!(!order_book.insert(&delta(1, 0, "99")));
+        assert!(!order_book.insert(&snapshot(10, "100", "101")));
+        assert!(!order_book.insert(&delta(11, 10, "99")));
+        assert!(order_book.synced);
+
+        // Asks for a snapshot once, ignoring deltas until it comes
+        assert!(order_book.insert(&delta(13, 12, "98")));
+        assert!(!order_book.synced);
+        assert!(!order_book.insert(&delta(14, 13, "97")));
+        assert_eq!(order_book.bids, [(100.0, 1.0), (99.0, 2.0)]);
+
+        assert!(!order_book.insert(&snapshot(20, "102", "103")));
+        assert!(!order_book.insert(&delta(21, 20, "101")));
+        assert!(order_book.synced);
+        assert_eq!(order_book.bids, [(102.0, 1.0), (101.0, 2.0)]);
+        assert_eq!(order_book.t, 21);
+    }
+
+    fn tkrs(times: &[i64]) -> TkrResult {
+        let data = times
+            .iter()
+            .map(|&t| TkrData {
+                last: Some(Decimal::from(t)),
+                t,
+                ..TkrData::default()
+            })
+            .collect();
+        ChannelResult::new("crypto.com", "BTCUSD-PERP", "ticker", data)
+    }
+
+    fn held(list: &DataList) -> Vec<i64> {
+        list.data.iter().map(|data| data.t).collect()
+    }
+
+    #[test]
+    fn keeps_every_ticker_of_an_update_in_order() {
+        let mut list = DataList::new(10);
+        assert_eq!(list.insert(&tkrs(&[30, 10, 20])), 3);
+        assert_eq!(held(&list), [10, 20, 30]);
+        assert_eq!(list.latest().unwrap().last, Some(Decimal::from(30)));
+
+        // The latest again, as a resubscribe sends it, is skipped quietly
+        assert_eq!(list.insert(&tkrs(&[30, 40])), 1);
+        assert_eq!(held(&list), [10, 20, 30, 40]);
+        assert_eq!((list.rejected, list.malformed), (0, 0));
+    }
+
+    #[test]
+    fn counts_late_and_malformed_tickers() {
+        let mut list = DataList::new(10);
+        list.insert(&tkrs(&[100]));
+        assert_eq!(list.insert(&tkrs(&[50, 150, 99])), 1);
+        assert_eq!(list.rejected, 2);
+
+        assert_eq!(list.insert(&tkrs(&[])), 0);
+        assert_eq!(list.insert(&tkrs(&[0, -5, 200])), 1);
+        assert_eq!(list.malformed, 3);
+        assert_eq!(held(&list), [100, 150, 200]);
+    }
+
+    fn last_of(json: &str) -> Result<Option<Decimal>, serde_json::Error> {
+        let text = format!(r#"{{"i":"BTCUSD-PERP","t":1{}}}"#, json);
+        serde_json::from_str::<TkrData>(&text).map(|data| data.last)
+    }
+
+    #[test]
+    fn reads_optional_decimals() {
+        assert_eq!(last_of("").unwrap(), None);
+        assert_eq!(last_of(r#","a":null"#).unwrap(), None);
+        assert_eq!(last_of(r#","a":"""#).unwrap(), None);
+        assert_eq!(
+            last_of(r#","a":"70000.10""#).unwrap(),
+            Some(Decimal::new(7000010, 2))
+        );
+        assert_eq!(
+            last_of(r#","a":70000.5"#).unwrap(),
+            Some(Decimal::new(700005, 1))
+        );
+        assert_eq!(last_of(r#","a":0.1"#).unwrap(), Some(Decimal::new(1, 1)));
+        assert_eq!(
+            last_of(r#","a":"1.5e3""#).unwrap(),
+            Some(Decimal::from(1500))
+        );
+        assert_eq!(last_of(r#","a":"2E-4""#).unwrap(), Some(Decimal::new(2, 4)));
+        assert_eq!(last_of(r#","a":1.5e3"#).unwrap(), Some(Decimal::from(1500)));
+
+        // Errors for the frame to be dropped, rather than a panic or a zero
+        for garbage in [
+            r#""abc""#,
+            r#""1.2.3""#,
+            r#"" ""#,
+            "true",
+            "[1]",
+            "{}",
+            "1e40",
+        ] {
+            assert!(
+                last_of(&format!(r#","a":{}"#, garbage)).is_err(),
+                "{}",
+                garbage
+            );
         }
     }
 
-    pub fn insert(&mut self, tkr_result: &TkrResult) {
-        let data = tkr_result.data[0].clone();
-        self.data[self.insert_i] = data;
-        self.insert_i = (self.insert_i + 1) % self.capacity;
-        self.curr_i = (self.capacity - 1) - ((self.capacity - self.insert_i) % self.capacity)
+    // On an hour, so on a boundary of every interval
+    const HOUR: i64 = 1_760_000_400_000;
+
+    fn starts(series: &CandleSeries) -> Vec<i64> {
+        series.candles.iter().map(|candle| candle.start).collect()
+    }
+
+    #[test]
+    fn buckets_trades_by_interval() {
+        for interval in Interval::ALL {
+            let millis = interval.millis();
+            let mut series = CandleSeries::new(interval, 10);
+            series.update(HOUR - 1, 1.0, 1.0);
+            series.update(HOUR, 2.0, 1.0);
+            series.update(HOUR + millis - 1, 3.0, 1.0);
+            series.update(HOUR + millis, 4.0, 1.0);
+            assert_eq!(
+                starts(&series),
+                [HOUR - millis, HOUR, HOUR + millis],
+                "{}",
+                interval.label()
+            );
+            let candle = series.candles.iter().nth(1).unwrap();
+            assert_eq!((candle.open, candle.close, candle.volume), (2.0, 3.0, 2.0));
+        }
     }
 
-    pub fn get_order(&self) -> Vec<usize> {
-        let mut i = self.curr_i;
-        let mut order = Vec::with_capacity(self.capacity);
-        for _ in 0..(self.capacity - 1) {
-            order.push(i);
-            i = (self.capacity - 1) - ((self.capacity - i) % self.capacity);
+    #[test]
+    fn accumulates_ohlcv_within_a_bucket() {
+        let mut series = CandleSeries::new(Interval::M1, 10);
+        for (t, price, qty) in [
+            (0, 100.0, 1.0),
+            (10, 104.0, 0.5),
+            (20, 97.0, 2.0),
+            (59_999, 101.0, 0.25),
+        ] {
+            series.update(HOUR + t, price, qty);
         }
-        order
+        assert_eq!(
+            series.candles.newest(),
+            Some(&Candle {
+                start: HOUR,
+                open: 100.0,
+                high: 104.0,
+                low: 97.0,
+                close: 101.0,
+                volume: 3.75,
+            })
+        );
+    }
+
+    #[test]
+    fn adds_late_trades_to_held_buckets_only() {
+        let mut series = CandleSeries::new(Interval::S1, 10);
+        series.update(HOUR, 100.0, 1.0);
+        series.update(HOUR + 3_000, 110.0, 1.0);
+
+        // Into the bucket it belongs to, taken as that bucket's latest trade
+        series.update(HOUR + 500, 90.0, 2.0);
+        let first = *series.candles.oldest().unwrap();
+        assert_eq!((first.low, first.close, first.volume), (90.0, 90.0, 3.0));
+
+        // No candle for a quiet second is made up after the fact
+        series.update(HOUR + 1_500, 95.0, 1.0);
+        assert_eq!(starts(&series), [HOUR, HOUR + 3_000]);
+        assert_eq!(series.candles.newest().unwrap().close, 110.0);
+    }
+
+    #[test]
+    fn feeds_every_interval() {
+        let trade = |t: i64, price: f64| Trade {
+            id: t.to_string(),
+            timestamp: HOUR + t,
+            price,
+            qty: 1.0,
+            side: Side::Buy,
+        };
+        let mut candles = Candles::new(10);
+        candles.insert(&[trade(0, 1.0), trade(1_000, 2.0), trade(60_000, 3.0)]);
+        let counts: Vec<usize> = Interval::ALL
+            .iter()
+            .map(|interval| candles.get(*interval).candles.len())
+            .collect();
+        assert_eq!(counts, [3, 2, 2, 1, 1, 1]);
+        assert_eq!(
+            candles.get(Interval::H1).candles.newest().unwrap().volume,
+            3.0
+        );
     }
 }
@@ -0,0 +1,249 @@
+use crate::config::validate_instrument;
+use crate::models::{parse_decimal, Instrument};
+use anyhow::{anyhow, Context, Result};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Quote currencies stripped to find the asset of an instrument name
+const QUOTES: [&str; 4] = ["USDT", "USDC", "USD", "EUR"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Long,
+    Short,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Position {
+    pub instrument: String,
+    pub side: Direction,
+    pub qty: Decimal,
+    pub entry: Decimal, // average entry price
+}
+
+impl Position {
+    /// Parses a position typed in the Tui, e.g. "BTCUSD-PERP long 0.5 @ 65000"
+    pub fn parse(text: &str) -> Result<Self> {
+        let words: Vec<&str> = text.split_whitespace().filter(|w| *w != "@").collect();
+        let [instrument, side, qty, entry] = words.as_slice() else {
+            return Err(anyhow!("expected e.g. \"BTCUSD-PERP long 0.5 @ 65000\""));
+        };
+        let side = match side.to_ascii_lowercase().as_str() {
+            "long" | "buy" => Direction::Long,
+            "short" | "sell" => Direction::Short,
+            _ => return Err(anyhow!("side must be long or short: {:?}", side)),
+        };
+        let position = Self {
+            instrument: instrument.to_ascii_uppercase(),
+            side,
+            qty: parse_decimal(qty)?,
+            entry: parse_decimal(entry)?,
+        };
+        position.validate()?;
+        Ok(position)
+    }
+
+    fn validate(&self) -> Result<()> {
+        validate_instrument(&self.instrument)?;
+        if self.qty <= Decimal::ZERO || self.entry <= Decimal::ZERO {
+            return Err(anyhow!(
+                "{}: qty and entry must be above 0",
+                self.instrument
+            ));
+        }
+        Ok(())
+    }
+
+    /// Signed notional at `mark`, negative when short. None from here on
+    /// means the figure is beyond what a Decimal holds.
+    pub fn value(&self, mark: Decimal) -> Option<Decimal> {
+        let value = self.qty.checked_mul(mark)?;
+        match self.side {
+            Direction::Long => Some(value),
+            Direction::Short => Some(-value),
+        }
+    }
+
+    pub fn cost(&self) -> Option<Decimal> {
+        self.qty.checked_mul(self.entry)
+    }
+
+    pub fn pnl(&self, mark: Decimal) -> Option<Decimal> {
+        self.value(mark)?.checked_sub(self.value(self.entry)?)
+    }
+}
+
+/// Asset an instrument trades, from the catalogue when it knows the
+/// instrument, else from the name: BTCUSD-PERP, BTC_USDT and BTCUSDT are BTC
+pub fn asset(tkr: &str, instruments: &[Instrument]) -> String {
+    if let Some(inst) = instruments.iter().find(|inst| inst.symbol == tkr) {
+        if !inst.base_ccy.is_empty() {
+            return inst.base_ccy.clone();
+        }
+    }
+    let name = tkr.split(['_', '-']).next().unwrap_or(tkr);
+    QUOTES
+        .iter()
+        .find_map(|quote| name.strip_suffix(quote).filter(|base| !base.is_empty()))
+        .unwrap_or(name)
+        .to_string()
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct PositionsFile {
+    #[serde(default)]
+    positions: Vec<Position>,
+}
+
+/// Open positions, kept in a TOML file that can be edited by hand as well
+pub struct Positions {
+    path: Option<PathBuf>,
+    pub positions: Vec<Position>,
+}
+
+impl Positions {
+    /// `$XDG_CONFIG_HOME/crypto_tui/positions.toml` or the platform equivalent
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("crypto_tui").join("positions.toml"))
+    }
+
+    pub fn load(path: Option<PathBuf>) -> Result<Self> {
+        let positions = match path.as_deref() {
+            Some(path) if path.exists() => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("reading positions {}", path.display()))?;
+                let file: PositionsFile = toml::from_str(&text)
+                    .with_context(|| format!("parsing positions {}", path.display()))?;
+                for position in file.positions.iter() {
+                    position
+                        .validate()
+                        .with_context(|| format!("in positions {}", path.display()))?;
+                }
+                file.positions
+            }
+            _ => Vec::new(),
+        };
+        Ok(Self { path, positions })
+    }
+
+    pub fn add(&mut self, text: &str) -> Result<()> {
+        self.positions.push(Position::parse(text)?);
+        if let Err(err) = self.save() {
+            self.positions.pop();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Removes position `n`, counted from 1 as listed
+    pub fn remove(&mut self, n: usize) -> Result<()> {
+        if n == 0 || n > self.positions.len() {
+            return Err(anyhow!("no position #{}", n));
+        }
+        let position = self.positions.remove(n - 1);
+        if let Err(err) = self.save() {
+            self.positions.insert(n - 1, position);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = self.path.as_deref() else {
+            return Ok(());
+        };
+        write_positions(path, &self.positions)
+            .with_context(|| format!("saving positions {}", path.display()))
+    }
+}
+
+fn write_positions(path: &Path, positions: &[Position]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = PositionsFile {
+        positions: positions.to_vec(),
+    };
+    fs::write(path, toml::to_string(&file)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("crypto_tui-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn values_long_and_short_positions() {
+        let long = Position::parse("BTCUSDT long 2 @ 100").unwrap();
+        let short = Position::parse("BTCUSDT short 2 @ 100").unwrap();
+        let mark = Decimal::from(110);
+        assert_eq!(long.value(mark), Some(Decimal::from(220)));
+        assert_eq!(long.pnl(mark), Some(Decimal::from(20)));
+        assert_eq!(short.value(mark), Some(Decimal::from(-220)));
+        assert_eq!(short.pnl(mark), Some(Decimal::from(-20)));
+        assert_eq!(short.cost(), Some(Decimal::from(200)));
+    }
+
+    #[test]
+    fn overflows_to_none() {
+        let huge = Position::parse("BTCUSDT short 79228162514264337593543950335 @ 2").unwrap();
+        assert_eq!(huge.cost(), None);
+        assert_eq!(huge.value(Decimal::ONE), Some(-Decimal::MAX));
+        assert_eq!(huge.value(Decimal::TWO), None);
+        assert_eq!(huge.pnl(Decimal::ONE), None);
+    }
+
+    #[test]
+    fn keeps_exact_decimals() {
+        let path = temp_path("positions.toml");
+        let mut positions = Positions::load(Some(path.clone())).unwrap();
+        positions.add("BTCUSD-PERP long 0.1 @ 65000.07").unwrap();
+        positions
+            .add("ETH_USDT short 1.000000000000000001 @ 3456.78")
+            .unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("qty = \"0.1\""), "{}", text);
+        assert!(text.contains("entry = \"65000.07\""), "{}", text);
+        let loaded = Positions::load(Some(path.clone())).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.positions, positions.positions);
+        assert_eq!(loaded.positions[1].qty.to_string(), "1.000000000000000001");
+    }
+
+    #[test]
+    fn reads_hand_written_numbers() {
+        let file: PositionsFile = toml::from_str(
+            "[[positions]]\ninstrument = \"BTCUSDT\"\nside = \"long\"\nqty = 2\nentry = 0.5\n",
+        )
+        .unwrap();
+        assert_eq!(file.positions[0].qty, Decimal::TWO);
+        assert_eq!(file.positions[0].entry.to_string(), "0.5");
+    }
+
+    #[test]
+    fn failed_saves_leave_the_positions_alone() {
+        // A file where the directory should be makes every save fail
+        let blocker = temp_path("not-a-dir");
+        fs::write(&blocker, "").unwrap();
+        let mut positions = Positions {
+            path: Some(blocker.join("positions.toml")),
+            positions: vec![Position::parse("BTCUSDT long 1 @ 60000").unwrap()],
+        };
+
+        assert!(positions.add("ETHUSDT long 1 @ 3000").is_err());
+        assert_eq!(positions.positions.len(), 1);
+        assert!(positions.remove(1).is_err());
+        fs::remove_file(&blocker).unwrap();
+        assert_eq!(positions.positions[0].instrument, "BTCUSDT");
+    }
+}
@@ -0,0 +1,415 @@
+use crate::crash;
+use crate::feed::FeedTx;
+use crate::models::{ConnStatus, LinkState, WsCommand, WsEvent};
+use crate::sources::{self, cryptocom, Decoded, MarketDataSource, Protocol};
+use crate::ws_client::STATUS_INTERVAL;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use cli_log::*;
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::Receiver;
+use tokio::time::{self, interval, sleep_until, Interval};
+
+/// One line of a recording: a raw text frame, its venue and when it arrived
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Entry {
+    pub t: i64, // receive time, unix ms
+    #[serde(default = "default_venue")]
+    pub venue: String,
+    pub frame: String,
+}
+
+fn default_venue() -> String {
+    cryptocom::VENUE.to_string()
+}
+
+/// Writes frames to a gzip compressed newline-delimited JSON file
+pub struct Recorder {
+    out: GzEncoder<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("creating recording {}", path.display()))?;
+        Ok(Self {
+            out: GzEncoder::new(BufWriter::new(file), Compression::default()),
+        })
+    }
+
+    pub fn write(&mut self, venue: &str, frame: &str) -> Result<()> {
+        let entry = Entry {
+            t: Utc::now().timestamp_millis(),
+            venue: venue.to_string(),
+            frame: frame.to_string(),
+        };
+        serde_json::to_writer(&mut self.out, &entry)?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Makes everything written so far readable even if the process dies
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.out.finish()?.flush()?;
+        Ok(())
+    }
+}
+
+/// Entries of a recording in order. A truncated tail, e.g. from a crash
+/// mid-write, ends the iteration; malformed lines are skipped.
+pub struct Frames {
+    lines: Lines<BufReader<MultiGzDecoder<File>>>,
+}
+
+impl Frames {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("opening recording {}", path.display()))?;
+        Ok(Self {
+            lines: BufReader::new(MultiGzDecoder::new(file)).lines(),
+        })
+    }
+}
+
+impl Iterator for Frames {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        loop {
+            match self.lines.next()? {
+                Ok(line) => match serde_json::from_str(&line) {
+                    Ok(entry) => return Some(entry),
+                    Err(e) => debug!("Skipping bad recording line: {}", e),
+                },
+                Err(e) => {
+                    debug!("Recording ends early: {}", e);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+fn event_tkr(event: &WsEvent) -> Option<&str> {
+    match event {
+        WsEvent::Tkr(result) => Some(&result.tkr),
+        WsEvent::Book(result) => Some(&result.tkr),
+        WsEvent::Trade(result) => Some(&result.tkr),
+        WsEvent::Status(_) | WsEvent::SubAck(_) | WsEvent::Catalogue(_) | WsEvent::Delivery(_) => {
+            None
+        }
+    }
+}
+
+/// Decoders for the venues in a recording, created as they turn up
+#[derive(Default)]
+struct Decoders {
+    protocols: HashMap<String, Box<dyn Protocol>>,
+}
+
+impl Decoders {
+    fn decode(&mut self, entry: &Entry) -> Option<WsEvent> {
+        if !self.protocols.contains_key(&entry.venue) {
+            let protocol = sources::protocol(&entry.venue).or_else(|| {
+                debug!("Skipping frames from unknown venue {}", entry.venue);
+                None
+            })?;
+            self.protocols.insert(entry.venue.clone(), protocol);
+        }
+        let protocol = self.protocols.get_mut(&entry.venue)?;
+        match protocol.decode(&entry.frame) {
+            Ok(Decoded::Event(event)) => Some(event),
+            Ok(_) => None,
+            Err(e) => {
+                debug!("Skipping bad frame: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Venues and instruments with market data in a recording
+#[derive(Default)]
+pub struct Contents {
+    pub venues: Vec<String>,
+    pub instruments: Vec<String>,
+}
+
+/// Reads through a recording, listing what it holds in order of appearance
+pub fn scan(path: &Path) -> Result<Contents> {
+    let mut decoders = Decoders::default();
+    let mut contents = Contents::default();
+    for entry in Frames::open(path)? {
+        let Some(event) = decoders.decode(&entry) else {
+            continue;
+        };
+        if !contents.venues.contains(&entry.venue) {
+            contents.venues.push(entry.venue);
+        }
+        match event_tkr(&event) {
+            Some(tkr) if !contents.instruments.iter().any(|t| t == tkr) => {
+                contents.instruments.push(tkr.to_string())
+            }
+            _ => {}
+        }
+    }
+    Ok(contents)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    Rate(f64), // multiple of the recorded pace
+    Step,      // one frame per WsCommand::Step
+}
+
+/// Feeds a recording through the same WsEvent pipeline as the live client
+pub struct Replay {
+    frames: Frames,
+    decoders: Decoders,
+    tx: FeedTx,
+    cmd_rx: Receiver<WsCommand>,
+    watchlist: Vec<String>,
+    speed: Speed,
+    state: LinkState,
+    last_msg: HashMap<String, Instant>,
+    msg_count: u64,
+    window_start: Instant,
+}
+
+impl Replay {
+    pub fn new(
+        frames: Frames,
+        tx: FeedTx,
+        cmd_rx: Receiver<WsCommand>,
+        watchlist: Vec<String>,
+        speed: Speed,
+    ) -> Self {
+        Self {
+            frames,
+            decoders: Decoders::default(),
+            tx,
+            cmd_rx,
+            watchlist,
+            speed,
+            state: LinkState::Replaying,
+            last_msg: HashMap::new(),
+            msg_count: 0,
+            window_start: Instant::now(),
+        }
+    }
+
+    /// Replays to the end of the recording or until the receiver hangs up
+    async fn play(&mut self) -> Result<()> {
+        let mut status_timer = interval(STATUS_INTERVAL);
+        let mut prev_t: Option<i64> = None;
+
+        while let Some(entry) = self.frames.next() {
+            crash::remember(&entry.venue, &entry.frame);
+            let Some(event) = self.decoders.decode(&entry) else {
+                continue;
+            };
+            let tkr = match event_tkr(&event) {
+                Some(tkr) if self.watchlist.iter().any(|t| t == tkr) => tkr.to_string(),
+                _ => continue,
+            };
+
+            let delay = match (self.speed, prev_t) {
+                (Speed::Rate(rate), Some(prev)) => {
+                    Duration::from_millis((entry.t - prev).max(0) as u64).div_f64(rate)
+                }
+                _ => Duration::ZERO,
+            };
+            if !self.wait(delay, &mut status_timer).await {
+                return Ok(());
+            }
+            prev_t = Some(entry.t);
+
+            self.msg_count += 1;
+            if let WsEvent::Tkr(_) = event {
+                self.last_msg.insert(tkr, Instant::now());
+            }
+            // Waits for the Tui rather than dropping, so a replay is the
+            // same at any speed
+            if self.tx.send_wait(event).await.is_err() {
+                return Ok(());
+            }
+        }
+
+        debug!("Replay finished");
+        self.state = LinkState::ReplayEnded;
+        self.send_status().await;
+        Ok(())
+    }
+
+    /// Waits out `delay`, or for a step command when stepping, handling
+    /// commands and status updates meanwhile. False if the receiver hung up.
+    async fn wait(&mut self, delay: Duration, status_timer: &mut Interval) -> bool {
+        let step = self.speed == Speed::Step;
+        let deadline = time::Instant::now() + delay;
+        loop {
+            tokio::select! {
+                _ = self.tx.closed() => return false,
+                _ = sleep_until(deadline), if !step => return true,
+                Some(cmd) = self.cmd_rx.recv() => match cmd {
+                    WsCommand::Step if step => return true,
+                    // A recording plays back whatever gaps it has
+                    WsCommand::Step | WsCommand::ResyncBook(..) => {}
+                    WsCommand::Subscribe(tkr) => {
+                        if !self.watchlist.contains(&tkr) {
+                            self.watchlist.push(tkr);
+                        }
+                    }
+                    WsCommand::Unsubscribe(tkr) => {
+                        self.watchlist.retain(|t| *t != tkr);
+                        self.last_msg.remove(&tkr);
+                    }
+                },
+                _ = status_timer.tick() => self.send_status().await,
+            }
+        }
+    }
+
+    async fn send_status(&mut self) {
+        let elapsed = self.window_start.elapsed().as_secs_f64();
+        let msg_rate = if elapsed > 0.0 {
+            self.msg_count as f64 / elapsed
+        } else {
+            0.0
+        };
+        self.msg_count = 0;
+        self.window_start = Instant::now();
+
+        let status = ConnStatus {
+            venue: String::new(),
+            state: self.state,
+            last_msg: self.last_msg.clone(),
+            rtt: None,
+            msg_rate,
+            reconnects: 0,
+        };
+        let _ = self.tx.send_wait(WsEvent::Status(status)).await;
+    }
+}
+
+#[async_trait]
+impl MarketDataSource for Replay {
+    async fn connect(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn run(&mut self) -> Result<()> {
+        self.play().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed;
+    use crate::sources::binance;
+    use rust_decimal::Decimal;
+    use std::io::Read;
+    use std::path::PathBuf;
+    use tokio::sync::mpsc;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/binance.ndjson.gz");
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("crypto_tui-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn reads_back_what_was_written() {
+        let path = temp_path("round-trip.ndjson.gz");
+        let frames = [
+            (
+                cryptocom::VENUE,
+                r#"{"id":-1,"method":"subscribe","result":{}}"#,
+            ),
+            (
+                binance::VENUE,
+                "{\"stream\":\"btcusdt@ticker\",\"data\":{\"c\":\"1\\n2\"}}",
+            ),
+            (cryptocom::VENUE, "not json at all"),
+        ];
+        let mut recorder = Recorder::create(&path).unwrap();
+        for (venue, frame) in frames {
+            recorder.write(venue, frame).unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let entries: Vec<Entry> = Frames::open(&path).unwrap().collect();
+        let read: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|entry| (entry.venue.as_str(), entry.frame.as_str()))
+            .collect();
+        assert_eq!(read, frames);
+        assert!(entries.windows(2).all(|pair| pair[0].t <= pair[1].t));
+
+        // A recording cut off mid-write still gives the entries before the cut
+        let mut text = String::new();
+        MultiGzDecoder::new(File::open(&path).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+        let mut out = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        out.write_all(&text.as_bytes()[..text.len() - 10]).unwrap();
+        out.finish().unwrap();
+        assert_eq!(Frames::open(&path).unwrap().count(), 2);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn replays_a_recording_into_the_feed() {
+        let (tx, mut rx) = feed::channel();
+        let (_cmd_tx, cmd_rx) = mpsc::channel(16);
+        let mut replay = Replay::new(
+            Frames::open(Path::new(FIXTURE)).unwrap(),
+            tx,
+            cmd_rx,
+            vec!["BTCUSDT".to_string()],
+            Speed::Rate(1e6),
+        );
+        // Run alongside the reader, as replay waits for it
+        tokio::spawn(async move { replay.run().await });
+
+        let (mut trades, mut books, mut tkrs, mut last, mut ended) = (0, 0, 0, None, false);
+        while let Some(event) = rx.recv().await {
+            match event {
+                WsEvent::Trade(result) => {
+                    assert_eq!(result.tkr, "BTCUSDT");
+                    trades += result.data.len();
+                }
+                WsEvent::Book(result) => {
+                    assert_eq!(result.tkr, "BTCUSDT");
+                    books += 1;
+                }
+                WsEvent::Tkr(result) => {
+                    assert_eq!(
+                        (result.venue.as_str(), result.tkr.as_str()),
+                        (binance::VENUE, "BTCUSDT")
+                    );
+                    tkrs += result.data.len();
+                    last = result.data.last().unwrap().last;
+                }
+                WsEvent::Status(status) => ended |= status.state == LinkState::ReplayEnded,
+                event => panic!("unexpected {:?}", event),
+            }
+        }
+        // Only the watched instrument, every update of it
+        assert_eq!((trades, books, tkrs), (93, 60, 15));
+        assert_eq!(last, Some(Decimal::new(6697306, 2)));
+        assert!(ended);
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// Anything stored in a `TimeSeries`
+pub trait Timestamped {
+    /// Unix millis the entry belongs to
+    fn time(&self) -> i64;
+}
+
+/// Fixed capacity ring buffer of entries in time order; once full, each
+/// push evicts the oldest entry. Only entries actually pushed are held, so
+/// a fresh buffer iterates over nothing rather than default filled slots.
+#[derive(Clone, Debug)]
+pub struct TimeSeries<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T: Timestamped> TimeSeries<T> {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Appends `item` unless it is older than the newest entry, returning
+    /// whether it was kept. Equal times are allowed, e.g. fills of one order.
+    pub fn push(&mut self, item: T) -> bool {
+        if self
+            .newest()
+            .is_some_and(|newest| item.time() < newest.time())
+        {
+            return false;
+        }
+        if self.items.len() == self.capacity {
+            self.items.pop_front();
+        }
+        self.items.push_back(item);
+        true
+    }
+
+    pub fn newest(&self) -> Option<&T> {
+        self.items.back()
+    }
+
+    /// For entries that build up in place, like the current candle
+    pub fn newest_mut(&mut self) -> Option<&mut T> {
+        self.items.back_mut()
+    }
+
+    pub fn oldest(&self) -> Option<&T> {
+        self.items.front()
+    }
+
+    /// Oldest first; `.rev()` for newest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator {
+        self.items.iter()
+    }
+
+    pub fn iter_newest(&self) -> impl Iterator<Item = &T> {
+        self.items.iter().rev()
+    }
+
+    /// Entries with `time` in `range`, oldest first
+    pub fn range(&self, range: Range<i64>) -> impl DoubleEndedIterator<Item = &T> {
+        let start = self.items.partition_point(|item| item.time() < range.start);
+        let end = self.items.partition_point(|item| item.time() < range.end);
+        self.items.range(start..end.max(start))
+    }
+
+    /// Entries at or after `time`, oldest first
+    pub fn since(&self, time: i64) -> impl DoubleEndedIterator<Item = &T> {
+        self.range(time..i64::MAX)
+    }
+
+    /// The first entry stamped exactly `time`
+    pub fn get_mut(&mut self, time: i64) -> Option<&mut T> {
+        let i = self.items.partition_point(|item| item.time() < time);
+        self.items.get_mut(i).filter(|item| item.time() == time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Item {
+        t: i64,
+        id: usize,
+    }
+
+    impl Timestamped for Item {
+        fn time(&self) -> i64 {
+            self.t
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Push(i64),
+        Bump(i64), // get_mut(t) and change the entry
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            3 => (-20i64..20).prop_map(Op::Push),
+            1 => (-20i64..20).prop_map(Op::Bump),
+        ]
+    }
+
+    /// What a TimeSeries should hold: a plain Vec, oldest first
+    struct Model {
+        items: Vec<Item>,
+        capacity: usize,
+    }
+
+    impl Model {
+        fn push(&mut self, item: Item) -> bool {
+            if self.items.last().is_some_and(|last| item.t < last.t) {
+                return false;
+            }
+            self.items.push(item);
+            if self.items.len() > self.capacity {
+                self.items.remove(0);
+            }
+            true
+        }
+
+        fn get_mut(&mut self, t: i64) -> Option<&mut Item> {
+            self.items.iter_mut().find(|item| item.t == t)
+        }
+    }
+
+    /// Applies `ops` to both, checking they agree after every step
+    fn run(capacity: usize, ops: &[Op]) -> (TimeSeries<Item>, Model) {
+        let mut series = TimeSeries::new(capacity);
+        let mut model = Model {
+            items: Vec::new(),
+            capacity,
+        };
+        for (id, op) in ops.iter().enumerate() {
+            match *op {
+                Op::Push(t) => {
+                    let item = Item { t, id };
+                    assert_eq!(series.push(item), model.push(item));
+                }
+                Op::Bump(t) => {
+                    let found = series.get_mut(t).map(|item| {
+                        item.id += 1000;
+                        *item
+                    });
+                    let expected = model.get_mut(t).map(|item| {
+                        item.id += 1000;
+                        *item
+                    });
+                    assert_eq!(found, expected);
+                }
+            }
+            let items: Vec<Item> = series.iter().copied().collect();
+            assert_eq!(items, model.items);
+            assert_eq!(series.len(), model.items.len());
+            assert_eq!(series.is_empty(), model.items.is_empty());
+            assert_eq!(series.newest(), model.items.last());
+            assert_eq!(series.oldest(), model.items.first());
+            let newest_first: Vec<Item> = series.iter_newest().copied().collect();
+            let reversed: Vec<Item> = model.items.iter().rev().copied().collect();
+            assert_eq!(newest_first, reversed);
+        }
+        (series, model)
+    }
+
+    proptest! {
+        #[test]
+        fn behaves_like_a_vec(capacity in 1usize..8, ops in prop::collection::vec(op(), 0..60)) {
+            let (series, model) = run(capacity, &ops);
+            prop_assert!(series.len() <= capacity);
+            prop_assert!(model.items.windows(2).all(|pair| pair[0].t <= pair[1].t));
+        }
+
+        #[test]
+        fn range_and_since_match_a_filter(
+            capacity in 1usize..12,
+            ops in prop::collection::vec(op(), 0..40),
+            start in -25i64..25,
+            end in -25i64..25,
+        ) {
+            let (series, model) = run(capacity, &ops);
+            let found: Vec<Item> = series.range(start..end).copied().collect();
+            let expected: Vec<Item> = model
+                .items
+                .iter()
+                .filter(|item| (start..end).contains(&item.t))
+                .copied()
+                .collect();
+            prop_assert_eq!(found, expected);
+
+            let found: Vec<Item> = series.since(start).copied().collect();
+            let expected: Vec<Item> =
+                model.items.iter().filter(|item| item.t >= start).copied().collect();
+            prop_assert_eq!(found, expected);
+        }
+    }
+}
@@ -0,0 +1,442 @@
+use super::{Decoded, Protocol};
+use crate::models::{
+    parse_decimal, BookData, BookLevel, ChannelResult, Side, SubAck, TkrData, Trade, WsEvent,
+};
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use cli_log::*;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub const VENUE: &str = "binance";
+// Partial depth sends the top levels whole, so no REST snapshot is needed
+const DEPTH_STREAM: &str = "depth10@100ms";
+// Binance takes 5 messages a second, so a watchlist goes out in few frames;
+// a connection is capped at 1024 streams, some 340 instruments, anyway
+const TKRS_PER_FRAME: usize = 50;
+
+/// `<symbol>@ticker`; spot sends best bid/ask, futures leaves them out
+#[derive(Deserialize)]
+struct Ticker {
+    #[serde(rename = "E")]
+    time: i64,
+    #[serde(rename = "c")]
+    last: String,
+    #[serde(rename = "P")]
+    change_pct: String,
+    #[serde(rename = "h")]
+    high: String,
+    #[serde(rename = "l")]
+    low: String,
+    #[serde(rename = "v")]
+    volume: String,
+    #[serde(rename = "q")]
+    quote_volume: String,
+    #[serde(rename = "b")]
+    bid: Option<String>,
+    #[serde(rename = "a")]
+    ask: Option<String>,
+}
+
+/// `<symbol>@aggTrade`
+#[derive(Deserialize)]
+struct AggTrade {
+    #[serde(rename = "a")]
+    id: u64,
+    #[serde(rename = "T")]
+    time: i64,
+    #[serde(rename = "p")]
+    price: String,
+    #[serde(rename = "q")]
+    qty: String,
+    #[serde(rename = "m")]
+    buyer_is_maker: bool,
+}
+
+/// `<symbol>@depth10@100ms`; spot and futures name the fields differently
+#[derive(Deserialize)]
+struct Depth {
+    #[serde(rename = "lastUpdateId", alias = "u")]
+    update_id: u64,
+    #[serde(rename = "E")]
+    time: Option<i64>,
+    #[serde(rename = "bids", alias = "b")]
+    bids: Vec<(String, String)>,
+    #[serde(rename = "asks", alias = "a")]
+    asks: Vec<(String, String)>,
+}
+
+/// Binance combined streams (`/stream`), spot or USD-M futures by url.
+/// The server's pings are answered by tungstenite on the next read.
+#[derive(Default)]
+pub struct Binance {
+    next_id: u64,
+    names: HashMap<String, String>, // stream symbol -> dashboard instrument name
+    best: HashMap<String, (Decimal, Decimal)>, // latest (bid, ask) from depth, for futures tickers
+    pending: HashMap<u64, (Vec<String>, bool)>, // request id -> (tkrs, subscribe)
+}
+
+impl Binance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// One request for the streams of all of `tkrs`
+    fn request(&mut self, method: &str, tkrs: &[String]) -> String {
+        self.next_id += 1;
+        self.pending
+            .insert(self.next_id, (tkrs.to_vec(), method == "SUBSCRIBE"));
+        let params: Vec<String> = tkrs.iter().flat_map(|tkr| streams(tkr)).collect();
+        serde_json::json!({
+            "method": method,
+            "params": params,
+            "id": self.next_id,
+        })
+        .to_string()
+    }
+
+    fn ticker(&self, tkr: String, ticker: Ticker) -> Result<WsEvent> {
+        let best = self.best.get(&tkr);
+        let data = TkrData {
+            high: Some(parse_decimal(&ticker.high)?),
+            low: Some(parse_decimal(&ticker.low)?),
+            last: Some(parse_decimal(&ticker.last)?),
+            instrument: tkr.clone(),
+            volume: Some(parse_decimal(&ticker.volume)?),
+            volume_usd: Some(parse_decimal(&ticker.quote_volume)?),
+            // Binance gives the change in percent, crypto.com as a fraction
+            change: Some(parse_decimal(&ticker.change_pct)? / Decimal::ONE_HUNDRED),
+            bid: ticker
+                .bid
+                .as_deref()
+                .map(parse_decimal)
+                .transpose()?
+                .or_else(|| best.map(|(bid, _)| *bid)),
+            ask: ticker
+                .ask
+                .as_deref()
+                .map(parse_decimal)
+                .transpose()?
+                .or_else(|| best.map(|(_, ask)| *ask)),
+            t: ticker.time,
+            ..Default::default()
+        };
+        Ok(WsEvent::Tkr(ChannelResult::new(
+            VENUE,
+            &tkr,
+            "ticker",
+            vec![data],
+        )))
+    }
+
+    /// Matches a reply to the request it answers
+    fn ack(&mut self, reply: &serde_json::Value) -> Decoded {
+        let error = reply.get("error").map(|error| {
+            format!(
+                "{} ({})",
+                error["msg"].as_str().unwrap_or("request failed"),
+                error["code"]
+            )
+        });
+        let Some((tkrs, subscribe)) = reply["id"].as_u64().and_then(|id| self.pending.remove(&id))
+        else {
+            debug!("Unmatched reply: {}", reply);
+            return Decoded::Ignore;
+        };
+        match &error {
+            Some(error) => debug!("Request for {:?} refused: {}", tkrs, error),
+            None => debug!("Request for {:?} confirmed", tkrs),
+        }
+        // A request stands or falls as a whole
+        let acks = tkrs.into_iter().map(|tkr| {
+            WsEvent::SubAck(SubAck {
+                venue: VENUE.to_string(),
+                tkr,
+                subscribe,
+                error: error.clone(),
+            })
+        });
+        Decoded::Events(acks.collect())
+    }
+
+    fn depth(&mut self, tkr: String, depth: Depth) -> WsEvent {
+        let levels = |side: Vec<(String, String)>| -> Vec<BookLevel> {
+            side.into_iter()
+                .map(|(price, qty)| BookLevel(price, qty, String::new()))
+                .collect()
+        };
+        if let (Some(bid), Some(ask)) = (depth.bids.first(), depth.asks.first()) {
+            if let (Ok(bid), Ok(ask)) = (parse_decimal(&bid.0), parse_decimal(&ask.0)) {
+                self.best.insert(tkr.clone(), (bid, ask));
+            }
+        }
+        let data = BookData {
+            bids: levels(depth.bids),
+            asks: levels(depth.asks),
+            update: None,
+            t: depth.time.unwrap_or_else(|| Utc::now().timestamp_millis()),
+            u: depth.update_id,
+            pu: None,
+        };
+        WsEvent::Book(ChannelResult::new(VENUE, &tkr, "book", vec![data]))
+    }
+}
+
+impl Protocol for Binance {
+    fn venue(&self) -> &'static str {
+        VENUE
+    }
+
+    fn subscribe(&mut self, tkr: &str) -> Vec<String> {
+        self.subscribe_all(&[tkr.to_string()])
+    }
+
+    fn subscribe_all(&mut self, tkrs: &[String]) -> Vec<String> {
+        for tkr in tkrs {
+            self.names
+                .insert(symbol(tkr).to_lowercase(), tkr.to_string());
+        }
+        tkrs.chunks(TKRS_PER_FRAME)
+            .map(|chunk| self.request("SUBSCRIBE", chunk))
+            .collect()
+    }
+
+    fn unsubscribe(&mut self, tkr: &str) -> Vec<String> {
+        self.names.remove(&symbol(tkr).to_lowercase());
+        self.best.remove(tkr);
+        vec![self.request("UNSUBSCRIBE", &[tkr.to_string()])]
+    }
+
+    /// Partial depth sends whole books, so there is no gap to recover from
+    fn resync_book(&mut self, _tkr: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn decode(&mut self, text: &str) -> Result<Decoded> {
+        let mut json_value: serde_json::Value = serde_json::from_str(text)?;
+        // Anything but stream data: {"result":null,"id":1} acks or errors
+        let Some(stream) = json_value["stream"].as_str().map(str::to_string) else {
+            return Ok(self.ack(&json_value));
+        };
+        let (sym, kind) = stream
+            .split_once('@')
+            .ok_or_else(|| anyhow!("bad stream name {}", stream))?;
+        // Recordings replay without the subscribe, so fall back to the symbol
+        let tkr = self
+            .names
+            .get(sym)
+            .cloned()
+            .unwrap_or_else(|| sym.to_uppercase());
+        let data = json_value["data"].take();
+
+        let event = match kind {
+            "ticker" => self.ticker(tkr, serde_json::from_value(data)?)?,
+            "aggTrade" => {
+                let agg: AggTrade = serde_json::from_value(data)?;
+                let trade = Trade {
+                    id: agg.id.to_string(),
+                    timestamp: agg.time,
+                    price: agg.price.parse()?,
+                    qty: agg.qty.parse()?,
+                    // the maker is the buyer, so the taker sold
+                    side: if agg.buyer_is_maker {
+                        Side::Sell
+                    } else {
+                        Side::Buy
+                    },
+                };
+                WsEvent::Trade(ChannelResult::new(VENUE, &tkr, "trade", vec![trade]))
+            }
+            DEPTH_STREAM => self.depth(tkr, serde_json::from_value(data)?),
+            _ => {
+                debug!("Unmatched stream: {}", stream);
+                return Ok(Decoded::Ignore);
+            }
+        };
+        Ok(Decoded::Event(event))
+    }
+
+    fn disconnected(&mut self) {
+        self.pending.clear();
+    }
+}
+
+/// Ticker, trade and depth streams of `tkr`
+fn streams(tkr: &str) -> [String; 3] {
+    let sym = symbol(tkr).to_lowercase();
+    [
+        format!("{}@ticker", sym),
+        format!("{}@aggTrade", sym),
+        format!("{}@{}", sym, DEPTH_STREAM),
+    ]
+}
+
+/// Binance symbol for an instrument name. crypto.com style names map onto
+/// the USDT market (BTCUSD-PERP and BTC_USD become BTCUSDT); names without
+/// `_` or `-PERP`, such as BTCUSDT, are taken as Binance symbols already.
+pub fn symbol(tkr: &str) -> String {
+    if !(tkr.contains('_') || tkr.ends_with("-PERP")) {
+        return tkr.to_string();
+    }
+    let sym = tkr.strip_suffix("-PERP").unwrap_or(tkr).replace('_', "");
+    match sym.strip_suffix("USD") {
+        Some(base) => format!("{}USDT", base),
+        None => sym,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::Frames;
+    use crate::sources::mock::{event, MockServer};
+    use std::path::Path;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/binance.ndjson.gz");
+
+    #[test]
+    fn maps_instrument_names() {
+        assert_eq!(symbol("BTCUSD-PERP"), "BTCUSDT");
+        assert_eq!(symbol("BTC_USD"), "BTCUSDT");
+        assert_eq!(symbol("BTC_USDT"), "BTCUSDT");
+        assert_eq!(symbol("ETH_BTC"), "ETHBTC");
+        assert_eq!(symbol("BTCUSDT"), "BTCUSDT");
+    }
+
+    #[tokio::test]
+    async fn streams_recorded_frames() {
+        let mut server = MockServer::start().await;
+        let (mut rx, _cmd_tx) = server.client(Binance::new(), &["BTCUSD-PERP", "ETHUSD-PERP"]);
+        let mut conn = server.accept().await;
+
+        // The whole watchlist in one request
+        let sub = conn.recv().await;
+        assert_eq!(sub["method"], "SUBSCRIBE");
+        assert_eq!(sub["id"], 1);
+        assert_eq!(
+            sub["params"],
+            serde_json::json!([
+                "btcusdt@ticker",
+                "btcusdt@aggTrade",
+                "btcusdt@depth10@100ms",
+                "ethusdt@ticker",
+                "ethusdt@aggTrade",
+                "ethusdt@depth10@100ms",
+            ])
+        );
+        for entry in Frames::open(Path::new(FIXTURE)).unwrap() {
+            conn.send(&entry.frame).await;
+        }
+
+        // The recorded reply to id 1 acks both instruments; id 2 is unmatched
+        let (mut acks, mut trades, mut books) = (Vec::new(), Vec::new(), Vec::new());
+        let mut tickers: HashMap<String, usize> = HashMap::new();
+        while acks.len() < 2
+            || trades.len() < 179
+            || books.len() < 120
+            || tickers.values().sum::<usize>() < 30
+        {
+            match event(&mut rx).await {
+                WsEvent::SubAck(ack) => {
+                    assert!(ack.subscribe && ack.error.is_none());
+                    acks.push(ack.tkr);
+                }
+                WsEvent::Trade(result) => trades.push(result),
+                WsEvent::Book(result) => books.push(result),
+                WsEvent::Tkr(result) => {
+                    *tickers.entry(result.tkr).or_default() += result.data.len()
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(acks, ["BTCUSD-PERP", "ETHUSD-PERP"]);
+        assert_eq!(tickers["BTCUSD-PERP"], 15);
+        assert_eq!(tickers["ETHUSD-PERP"], 15);
+
+        let trade = trades.iter().find(|t| t.tkr == "BTCUSD-PERP").unwrap();
+        assert_eq!(trade.venue, VENUE);
+        assert_eq!(trade.data[0].id, "3100000001");
+        assert_eq!(trade.data[0].price, 66995.28);
+        assert_eq!(trade.data[0].qty, 0.0251);
+        assert_eq!(trade.data[0].side, Side::Sell); // buyer was the maker
+
+        let book = books.iter().find(|b| b.tkr == "BTCUSD-PERP").unwrap();
+        let data = &book.data[0];
+        assert_eq!((data.bids.len(), data.asks.len()), (10, 10));
+        assert_eq!(data.bids[0].0, "66995.27");
+        assert_eq!(data.asks[0].0, "66995.28");
+        assert_eq!(data.u, 70000000007);
+        assert!(data.update.is_none());
+        assert!(books.iter().all(|b| b.tkr.ends_with("USD-PERP")));
+    }
+
+    #[test]
+    fn forgets_requests_on_disconnect() {
+        let mut binance = Binance::new();
+        binance.subscribe("BTCUSD-PERP");
+        binance.disconnected();
+        let reply = r#"{"result":null,"id":1}"#;
+        assert!(matches!(binance.decode(reply).unwrap(), Decoded::Ignore));
+    }
+
+    #[test]
+    fn fills_futures_tickers_from_depth() {
+        let mut binance = Binance::new();
+        binance.subscribe("BTCUSD-PERP");
+        let depth = r#"{"stream":"btcusdt@depth10@100ms","data":{"E":1760000000000,"u":7,"b":[["100.5","1"]],"a":[["101.0","2"]]}}"#;
+        let ticker = r#"{"stream":"btcusdt@ticker","data":{"E":1760000000100,"c":"100.75","P":"-2.5","h":"105","l":"99","v":"10","q":"1000"}}"#;
+        assert!(matches!(
+            binance.decode(depth).unwrap(),
+            Decoded::Event(WsEvent::Book(_))
+        ));
+        let Decoded::Event(WsEvent::Tkr(result)) = binance.decode(ticker).unwrap() else {
+            panic!("expected a ticker");
+        };
+        assert_eq!(result.tkr, "BTCUSD-PERP");
+        let data = &result.data[0];
+        assert_eq!(data.bid, Some(Decimal::new(1005, 1)));
+        assert_eq!(data.ask, Some(Decimal::new(101, 0)));
+        assert_eq!(data.change, Some(Decimal::new(-25, 3)));
+    }
+
+    #[test]
+    fn batches_a_watchlist_into_few_frames() {
+        let mut binance = Binance::new();
+        let tkrs: Vec<String> = (0..20).map(|i| format!("TKR{}USDT", i)).collect();
+        let frames = binance.subscribe_all(&tkrs);
+        assert_eq!(frames.len(), 1);
+        let frame: serde_json::Value = serde_json::from_str(&frames[0]).unwrap();
+        assert_eq!(frame["params"].as_array().unwrap().len(), 60);
+        assert_eq!(frame["params"][57], "tkr19usdt@ticker");
+
+        let tkrs: Vec<String> = (0..120).map(|i| format!("TKR{}USDT", i)).collect();
+        assert_eq!(binance.subscribe_all(&tkrs).len(), 3);
+    }
+
+    #[test]
+    fn acks_every_instrument_of_a_request() {
+        let mut binance = Binance::new();
+        let tkrs = ["BTCUSDT".to_string(), "NOPEUSDT".to_string()];
+        binance.subscribe_all(&tkrs);
+        let reply = r#"{"error":{"code":2,"msg":"Invalid request"},"id":1}"#;
+        let Decoded::Events(events) = binance.decode(reply).unwrap() else {
+            panic!("expected acks");
+        };
+        let acks: Vec<(String, Option<String>)> = events
+            .into_iter()
+            .map(|event| match event {
+                WsEvent::SubAck(ack) => (ack.tkr, ack.error),
+                event => panic!("unexpected {:?}", event),
+            })
+            .collect();
+        let error = Some("Invalid request (2)".to_string());
+        assert_eq!(
+            acks,
+            [
+                ("BTCUSDT".to_string(), error.clone()),
+                ("NOPEUSDT".to_string(), error)
+            ]
+        );
+    }
+}
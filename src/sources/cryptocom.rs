@@ -0,0 +1,352 @@
+use super::{Decoded, Protocol};
+use crate::models::{BookData, Response, SubAck, TkrData, Trade, WsEvent};
+use anyhow::Result;
+use chrono::Utc;
+use cli_log::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub const VENUE: &str = "crypto.com";
+const BOOK_DEPTH: u32 = 10; // crypto.com offers 10 or 50
+
+#[derive(Deserialize)]
+struct Heartbeat {
+    id: u64,
+    // method: String,
+    // code: i64,
+}
+
+/// Reply to one of our requests; market data arrives with id -1 instead
+#[derive(Deserialize)]
+struct Reply {
+    id: i64,
+    #[serde(default)]
+    code: i64,
+    message: Option<String>,
+}
+
+/// crypto.com exchange v1 market data JSON-RPC
+#[derive(Default)]
+pub struct CryptoCom {
+    next_id: u64,
+    pending: HashMap<u64, (String, bool)>, // request id -> (tkr, subscribe)
+}
+
+impl CryptoCom {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn channels(&mut self, method: &str, tkr: &str) -> Vec<String> {
+        let channels = vec![
+            format!("ticker.{}", tkr),
+            book_channel(tkr),
+            format!("trade.{}", tkr),
+        ];
+        vec![self.request(method, tkr, channels)]
+    }
+
+    fn request(&mut self, method: &str, tkr: &str, channels: Vec<String>) -> String {
+        let mut params = serde_json::json!({ "channels": channels });
+        if method == "subscribe" {
+            params["book_subscription_type"] = "SNAPSHOT_AND_UPDATE".into();
+            params["book_update_frequency"] = 10.into();
+        }
+        self.next_id += 1;
+        self.pending
+            .insert(self.next_id, (tkr.to_string(), method == "subscribe"));
+        let tkr_sub = serde_json::json!({
+            "id": self.next_id,
+            "method": method,
+            "params": params,
+            "nonce": Utc::now().timestamp_millis(),
+        });
+        tkr_sub.to_string()
+    }
+
+    /// Matches a reply to the request it answers
+    fn ack(&mut self, reply: Reply) -> Decoded {
+        let Some((tkr, subscribe)) = u64::try_from(reply.id)
+            .ok()
+            .and_then(|id| self.pending.remove(&id))
+        else {
+            debug!("Reply to unknown request {}: code {}", reply.id, reply.code);
+            return Decoded::Ignore;
+        };
+        let error = (reply.code != 0).then(|| error_message(reply.code, reply.message));
+        match &error {
+            Some(error) => debug!("Request for {} refused: {}", tkr, error),
+            None => debug!("Request for {} confirmed", tkr),
+        }
+        Decoded::Event(WsEvent::SubAck(SubAck {
+            venue: VENUE.to_string(),
+            tkr,
+            subscribe,
+            error,
+        }))
+    }
+}
+
+fn book_channel(tkr: &str) -> String {
+    format!("book.{}.{}", tkr, BOOK_DEPTH)
+}
+
+/// Readable text for an exchange v1 response code
+fn error_message(code: i64, message: Option<String>) -> String {
+    let reason = match code {
+        40001 => "bad request",
+        40002 => "method not found",
+        40003 => "invalid request",
+        40004 => "missing or invalid argument, e.g. unknown instrument",
+        40006 => "duplicate request",
+        40101 => "unauthorized",
+        40102 => "invalid nonce",
+        40103 => "IP not allowed",
+        40107 => "too many subscriptions",
+        40401 => "not found",
+        40801 => "request timed out",
+        42901 => "too many requests",
+        50001 => "exchange internal error",
+        _ => "",
+    };
+    match (reason, message) {
+        ("", Some(message)) => format!("{} ({})", message, code),
+        ("", None) => format!("error code {}", code),
+        (reason, _) => format!("{} ({})", reason, code),
+    }
+}
+
+impl Protocol for CryptoCom {
+    fn venue(&self) -> &'static str {
+        VENUE
+    }
+
+    fn subscribe(&mut self, tkr: &str) -> Vec<String> {
+        self.channels("subscribe", tkr)
+    }
+
+    fn unsubscribe(&mut self, tkr: &str) -> Vec<String> {
+        self.channels("unsubscribe", tkr)
+    }
+
+    /// A book subscription starts with a snapshot
+    fn resync_book(&mut self, tkr: &str) -> Vec<String> {
+        vec![
+            self.request("unsubscribe", tkr, vec![book_channel(tkr)]),
+            self.request("subscribe", tkr, vec![book_channel(tkr)]),
+        ]
+    }
+
+    fn decode(&mut self, text: &str) -> Result<Decoded> {
+        let json_value: serde_json::Value = serde_json::from_str(text)?;
+
+        let Some(method) = json_value.get("method").and_then(|m| m.as_str()) else {
+            debug!("Frame without method: {}", text);
+            return Ok(Decoded::Ignore);
+        };
+        if method == "public/heartbeat" {
+            let heartbeat: Heartbeat = serde_json::from_value(json_value)?;
+            let reply = serde_json::json!({
+                "id": heartbeat.id,
+                "method": "public/respond-heartbeat",
+            });
+            return Ok(Decoded::Reply(reply.to_string()));
+        }
+        if method != "subscribe" && method != "unsubscribe" {
+            debug!("Unmatched json: {:#?}", json_value);
+            return Ok(Decoded::Ignore);
+        }
+        if json_value.get("result").is_none() {
+            return Ok(self.ack(serde_json::from_value(json_value)?));
+        }
+
+        let channel = json_value["result"]["channel"].as_str().unwrap_or("");
+        if channel == "ticker" {
+            let mut tkr_resp: Response<TkrData> = serde_json::from_value(json_value)?;
+            // debug!("{:#?}", tkr_resp);
+            tkr_resp.result.venue = VENUE.to_string();
+            return Ok(Decoded::Event(WsEvent::Tkr(tkr_resp.result)));
+        } else if channel == "book" || channel == "book.update" {
+            let mut book_resp: Response<BookData> = serde_json::from_value(json_value)?;
+            book_resp.result.venue = VENUE.to_string();
+            return Ok(Decoded::Event(WsEvent::Book(book_resp.result)));
+        } else if channel == "trade" {
+            let mut trade_resp: Response<Trade> = serde_json::from_value(json_value)?;
+            trade_resp.result.venue = VENUE.to_string();
+            return Ok(Decoded::Event(WsEvent::Trade(trade_resp.result)));
+        }
+        debug!("Unmatched channel: {:#?}", json_value);
+        Ok(Decoded::Ignore)
+    }
+
+    fn disconnected(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::WsCommand;
+    use crate::sources::mock::{event, MockServer};
+    use rust_decimal::Decimal;
+
+    const TICKER: &str = r#"{"id":-1,"method":"subscribe","code":0,"result":{"instrument_name":"BTCUSD-PERP","subscription":"ticker.BTCUSD-PERP","channel":"ticker","data":[{"h":"51790.00","l":"47895.50","a":"51174.500","c":"0.0379","b":"51170.000","k":"51180.000","i":"BTCUSD-PERP","v":"879.5024","vv":"26370000.12","oi":null,"t":1613580710768}]}}"#;
+    const TRADE: &str = r#"{"id":-1,"method":"subscribe","code":0,"result":{"instrument_name":"BTCUSD-PERP","subscription":"trade.BTCUSD-PERP","channel":"trade","data":[{"d":"2030407068","t":1613581138462,"p":"51327.500","q":"0.000100","s":"BUY","i":"BTCUSD-PERP"}]}}"#;
+    const BOOK: &str = r#"{"id":-1,"method":"subscribe","code":0,"result":{"instrument_name":"BTCUSD-PERP","subscription":"book.BTCUSD-PERP.10","channel":"book","depth":10,"data":[{"asks":[["50126.0","0.4","2"]],"bids":[["50113.5","0.4","3"]],"t":1613580710768,"u":1613580710768}]}}"#;
+
+    #[tokio::test]
+    async fn subscribes_and_decodes() {
+        let mut server = MockServer::start().await;
+        let (mut rx, _cmd_tx) = server.client(CryptoCom::new(), &["BTCUSD-PERP"]);
+        let mut conn = server.accept().await;
+
+        let sub = conn.recv().await;
+        assert_eq!(sub["method"], "subscribe");
+        assert_eq!(
+            sub["params"]["channels"],
+            serde_json::json!([
+                "ticker.BTCUSD-PERP",
+                "book.BTCUSD-PERP.10",
+                "trade.BTCUSD-PERP"
+            ])
+        );
+        assert_eq!(
+            sub["params"]["book_subscription_type"],
+            "SNAPSHOT_AND_UPDATE"
+        );
+        conn.send(&format!(
+            r#"{{"id":{},"method":"subscribe","code":0}}"#,
+            sub["id"]
+        ))
+        .await;
+        let WsEvent::SubAck(ack) = event(&mut rx).await else {
+            panic!("expected an ack");
+        };
+        assert_eq!((ack.tkr.as_str(), ack.subscribe), ("BTCUSD-PERP", true));
+        assert_eq!(ack.error, None);
+
+        conn.send(TICKER).await;
+        let WsEvent::Tkr(tkr) = event(&mut rx).await else {
+            panic!("expected a ticker");
+        };
+        assert_eq!(
+            (tkr.venue.as_str(), tkr.tkr.as_str()),
+            (VENUE, "BTCUSD-PERP")
+        );
+        let data = &tkr.data[0];
+        assert_eq!(data.last, Some(Decimal::new(51174500, 3)));
+        assert_eq!(data.bid, Some(Decimal::new(51170, 0)));
+        assert_eq!(data.open_interest, None);
+
+        conn.send(TRADE).await;
+        let WsEvent::Trade(trade) = event(&mut rx).await else {
+            panic!("expected a trade");
+        };
+        assert_eq!(trade.data[0].price, 51327.5);
+        assert_eq!(trade.data[0].side, crate::models::Side::Buy);
+
+        conn.send(BOOK).await;
+        let WsEvent::Book(book) = event(&mut rx).await else {
+            panic!("expected a book");
+        };
+        assert_eq!(book.data[0].asks[0].0, "50126.0");
+        assert_eq!(book.data[0].bids.len(), 1);
+    }
+
+    #[test]
+    fn forgets_requests_on_disconnect() {
+        let mut cryptocom = CryptoCom::new();
+        cryptocom.subscribe("BTCUSD-PERP");
+        cryptocom.disconnected();
+        let reply = r#"{"id":1,"method":"subscribe","code":0}"#;
+        assert!(matches!(cryptocom.decode(reply).unwrap(), Decoded::Ignore));
+
+        cryptocom.subscribe("BTCUSD-PERP");
+        let reply = r#"{"id":2,"method":"subscribe","code":0}"#;
+        assert!(matches!(
+            cryptocom.decode(reply).unwrap(),
+            Decoded::Event(WsEvent::SubAck(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn answers_heartbeats() {
+        let mut server = MockServer::start().await;
+        let (_rx, _cmd_tx) = server.client(CryptoCom::new(), &[]);
+        let mut conn = server.accept().await;
+
+        conn.send(r#"{"id":1587523073344,"method":"public/heartbeat","code":0}"#)
+            .await;
+        let reply = conn.recv().await;
+        assert_eq!(reply["method"], "public/respond-heartbeat");
+        assert_eq!(reply["id"], 1587523073344u64);
+    }
+
+    #[tokio::test]
+    async fn reports_refused_requests() {
+        let mut server = MockServer::start().await;
+        let (mut rx, cmd_tx) = server.client(CryptoCom::new(), &[]);
+        let mut conn = server.accept().await;
+
+        cmd_tx
+            .send(WsCommand::Subscribe("NOPE-PERP".to_string()))
+            .await
+            .unwrap();
+        let sub = conn.recv().await;
+        conn.send(&format!(
+            r#"{{"id":{},"method":"subscribe","code":40004,"message":"INVALID_INSTRUMENT"}}"#,
+            sub["id"]
+        ))
+        .await;
+        let WsEvent::SubAck(ack) = event(&mut rx).await else {
+            panic!("expected an ack");
+        };
+        assert_eq!(ack.tkr, "NOPE-PERP");
+        assert_eq!(
+            ack.error.as_deref(),
+            Some("missing or invalid argument, e.g. unknown instrument (40004)")
+        );
+    }
+
+    #[tokio::test]
+    async fn resubscribes_a_book_out_of_sequence() {
+        let mut server = MockServer::start().await;
+        let (_rx, cmd_tx) = server.client(CryptoCom::new(), &["BTCUSD-PERP"]);
+        let mut conn = server.accept().await;
+        conn.recv().await;
+
+        // Other venues' gaps are theirs to fix
+        for venue in [VENUE, "binance"] {
+            cmd_tx
+                .send(WsCommand::ResyncBook(
+                    venue.to_string(),
+                    "BTCUSD-PERP".to_string(),
+                ))
+                .await
+                .unwrap();
+        }
+        cmd_tx
+            .send(WsCommand::Subscribe("ETHUSD-PERP".to_string()))
+            .await
+            .unwrap();
+        let unsub = conn.recv().await;
+        assert_eq!(unsub["method"], "unsubscribe");
+        assert_eq!(
+            unsub["params"]["channels"],
+            serde_json::json!(["book.BTCUSD-PERP.10"])
+        );
+        let sub = conn.recv().await;
+        assert_eq!(sub["method"], "subscribe");
+        assert_eq!(
+            sub["params"]["channels"],
+            serde_json::json!(["book.BTCUSD-PERP.10"])
+        );
+        assert_eq!(
+            sub["params"]["book_subscription_type"],
+            "SNAPSHOT_AND_UPDATE"
+        );
+        let next = conn.recv().await;
+        assert_eq!(next["params"]["channels"][0], "ticker.ETHUSD-PERP");
+    }
+}
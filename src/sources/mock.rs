@@ -0,0 +1,124 @@
+use super::Protocol;
+use crate::feed::{self, FeedRx};
+use crate::models::{ConnStatus, WsCommand, WsEvent};
+use crate::ws_client::WsClient;
+use futures_util::{SinkExt, StreamExt};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::time::timeout;
+use tokio_tungstenite::{accept_async, tungstenite::protocol::Message, WebSocketStream};
+
+// Long enough for a couple of reconnect backoffs
+const WAIT: Duration = Duration::from_secs(10);
+
+pub struct MockServer {
+    pub url: String,
+    conns: Receiver<MockConn>,
+    answering: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (tx, conns) = mpsc::channel(4);
+        let answering = Arc::new(AtomicBool::new(true));
+        let answer = answering.clone();
+        tokio::spawn(async move {
+            let mut unanswered = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                if !answer.load(Ordering::Relaxed) {
+                    unanswered.push(stream);
+                    continue;
+                }
+                if let Ok(ws) = accept_async(stream).await {
+                    if tx.send(MockConn { ws }).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Self {
+            url,
+            conns,
+            answering,
+        }
+    }
+
+    /// Whether to answer websocket upgrades; connections arriving while
+    /// not are held open in silence
+    pub fn answer(&self, answering: bool) {
+        self.answering.store(answering, Ordering::Relaxed);
+    }
+
+    /// Waits for the client to connect
+    pub async fn accept(&mut self) -> MockConn {
+        timeout(WAIT, self.conns.recv())
+            .await
+            .expect("no connection")
+            .unwrap()
+    }
+
+    /// Runs a WsClient speaking `protocol` against this server
+    pub fn client<P: Protocol + 'static>(
+        &self,
+        protocol: P,
+        watchlist: &[&str],
+    ) -> (FeedRx, Sender<WsCommand>) {
+        use super::MarketDataSource;
+        let (tx, rx) = feed::channel();
+        let (cmd_tx, cmd_rx) = mpsc::channel(16);
+        let watchlist = watchlist.iter().map(|tkr| tkr.to_string()).collect();
+        let mut client = WsClient::new(&self.url, protocol, tx, cmd_rx, watchlist);
+        tokio::spawn(async move { client.run().await });
+        (rx, cmd_tx)
+    }
+}
+
+/// One client connection; dropping it drops the socket without a close frame
+pub struct MockConn {
+    ws: WebSocketStream<TcpStream>,
+}
+
+impl MockConn {
+    /// Next text frame from the client as JSON, skipping pings
+    pub async fn recv(&mut self) -> serde_json::Value {
+        loop {
+            let msg = timeout(WAIT, self.ws.next())
+                .await
+                .expect("no frame from the client")
+                .expect("client hung up")
+                .unwrap();
+            if let Message::Text(text) = msg {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    pub async fn send(&mut self, text: &str) {
+        self.ws.send(Message::Text(text.to_string())).await.unwrap();
+    }
+}
+
+/// Next event that isn't a link status
+pub async fn event(rx: &mut FeedRx) -> WsEvent {
+    loop {
+        match timeout(WAIT, rx.recv()).await.expect("no event").unwrap() {
+            WsEvent::Status(_) => {}
+            event => return event,
+        }
+    }
+}
+
+/// Next link status, skipping market data
+pub async fn status(rx: &mut FeedRx) -> ConnStatus {
+    loop {
+        if let WsEvent::Status(status) = timeout(WAIT, rx.recv()).await.expect("no status").unwrap()
+        {
+            return status;
+        }
+    }
+}
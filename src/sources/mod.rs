@@ -0,0 +1,131 @@
+pub mod binance;
+pub mod cryptocom;
+// A local websocket server standing in for a venue
+#[cfg(test)]
+pub mod mock;
+
+use crate::models::{WsCommand, WsEvent};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures_util::future::try_join_all;
+use tokio::sync::mpsc::{Receiver, Sender};
+
+/// A feed of normalized WsEvents for the Tui: a live venue or a replay
+#[async_trait]
+pub trait MarketDataSource: Send {
+    /// Opens the first connection so startup errors surface before the Tui
+    async fn connect(&mut self) -> Result<()>;
+
+    /// Streams until the receiving side hangs up
+    async fn run(&mut self) -> Result<()>;
+
+    /// Flushes anything buffered, e.g. a recording; safe to call twice
+    fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Several sources run together behind one command channel, e.g. one per
+/// venue when comparing prices. Commands are copied to every source.
+pub struct Fanout {
+    sources: Vec<Box<dyn MarketDataSource>>,
+    cmd_txs: Vec<Sender<WsCommand>>,
+    cmd_rx: Receiver<WsCommand>,
+}
+
+impl Fanout {
+    pub fn new(cmd_rx: Receiver<WsCommand>) -> Self {
+        Self {
+            sources: Vec::new(),
+            cmd_txs: Vec::new(),
+            cmd_rx,
+        }
+    }
+
+    /// Adds a source along with the sender of its own command channel
+    pub fn push(&mut self, source: Box<dyn MarketDataSource>, cmd_tx: Sender<WsCommand>) {
+        self.sources.push(source);
+        self.cmd_txs.push(cmd_tx);
+    }
+}
+
+#[async_trait]
+impl MarketDataSource for Fanout {
+    async fn connect(&mut self) -> Result<()> {
+        for source in self.sources.iter_mut() {
+            source.connect().await?;
+        }
+        Ok(())
+    }
+
+    async fn run(&mut self) -> Result<()> {
+        let cmd_rx = &mut self.cmd_rx;
+        let cmd_txs = &self.cmd_txs;
+        let forward = async move {
+            while let Some(cmd) = cmd_rx.recv().await {
+                for cmd_tx in cmd_txs {
+                    let _ = cmd_tx.send(cmd.clone()).await;
+                }
+            }
+        };
+        let runs = try_join_all(self.sources.iter_mut().map(|source| source.run()));
+        tokio::pin!(runs);
+
+        // Sources stop once the event receiver hangs up, which may be after
+        // the command sender is gone
+        tokio::select! {
+            result = &mut runs => result?,
+            _ = forward => (&mut runs).await?,
+        };
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        for source in self.sources.iter_mut() {
+            source.close()?;
+        }
+        Ok(())
+    }
+}
+
+/// What a venue made of one text frame
+pub enum Decoded {
+    Event(WsEvent),
+    Events(Vec<WsEvent>), // e.g. an ack for each instrument of one request
+    Reply(String),        // e.g. a heartbeat response
+    Ignore,
+}
+
+/// A venue's websocket wire format, driven by `WsClient`
+pub trait Protocol: Send {
+    fn venue(&self) -> &'static str;
+
+    /// Frames subscribing to the ticker, book and trade channels of `tkr`
+    fn subscribe(&mut self, tkr: &str) -> Vec<String>;
+
+    /// Frames subscribing to the whole watchlist on connecting. Venues that
+    /// limit incoming messages combine the instruments into fewer frames.
+    fn subscribe_all(&mut self, tkrs: &[String]) -> Vec<String> {
+        tkrs.iter().flat_map(|tkr| self.subscribe(tkr)).collect()
+    }
+
+    fn unsubscribe(&mut self, tkr: &str) -> Vec<String>;
+
+    /// Frames getting a new book snapshot of `tkr` after a sequence gap
+    fn resync_book(&mut self, tkr: &str) -> Vec<String>;
+
+    fn decode(&mut self, text: &str) -> Result<Decoded>;
+
+    /// Forgets requests awaiting a reply once the link they went out on has
+    /// dropped, as they won't be answered
+    fn disconnected(&mut self);
+}
+
+/// Protocol by venue name, e.g. to decode a recording
+pub fn protocol(venue: &str) -> Option<Box<dyn Protocol>> {
+    match venue {
+        binance::VENUE => Some(Box::new(binance::Binance::new())),
+        cryptocom::VENUE => Some(Box::new(cryptocom::CryptoCom::new())),
+        _ => None,
+    }
+}
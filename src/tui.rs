@@ -1,20 +1,49 @@
+use crate::alerts::{Alerts, Fired};
+use crate::catalogue;
+use crate::components::alert_log::AlertLog;
 use crate::components::chart;
+use crate::components::compare::VenueComparison;
+use crate::components::picker::InstrumentPicker;
+use crate::components::portfolio::Portfolio;
+use crate::components::prompt::{Prompt, PromptMode};
+use crate::components::status_bar::StatusBar;
 use crate::components::summary;
-use crate::components::tkr_tab::TkrTabs;
-use crate::models::{DataList, TkrResult};
-use anyhow::Result;
+use crate::components::tkr_tab::{TabView, TkrTabs};
+use crate::config::{Action, Config};
+use crate::delivery::{Delivery, Hooks};
+use crate::feed::FeedRx;
+use crate::indicators::{Indicators, Study};
+use crate::models::{
+    Candles, ConnStatus, DataList, Instrument, Interval, LinkState, MarketKey, OrderBook, SubAck,
+    TradeList, WsCommand, WsEvent,
+};
+use crate::portfolio::Positions;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
 use cli_log::*;
+use futures_util::StreamExt;
 use ratatui::{
-    buffer::Buffer,
-    crossterm::event::{self, KeyCode, KeyEventKind},
-    layout::{Constraint, Layout},
-    prelude::Rect,
-    widgets::{Paragraph, Widget},
+    crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::Stylize,
+    text::{Line, Span},
+    widgets::Paragraph,
     DefaultTerminal, Frame,
 };
 use std::cmp::min;
-use std::collections::HashMap;
-use tokio::sync::mpsc::Receiver;
+use std::collections::{HashMap, VecDeque};
+use std::io::{stdout, Write};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::Sender;
+use tokio::time::{self, MissedTickBehavior};
+
+// Trade tape size filter steps, cycled with the filter keys
+const MIN_QTY_STEPS: [f64; 6] = [0.0, 0.001, 0.01, 0.1, 1.0, 10.0];
+const PICKER_MATCHES: usize = 10;
+const FRAME: Duration = Duration::from_millis(33); // redraws are capped at ~30 fps
+const IDLE_REDRAW: Duration = Duration::from_secs(1);
+const DELIVERIES: usize = 100; // kept for the alerts pane
+const FLASH: Duration = Duration::from_secs(5); // header shows a fired alert this long
 
 #[derive(PartialEq)]
 enum AppState {
@@ -22,79 +51,485 @@ enum AppState {
     Quitting,
 }
 
-pub struct Tui<'a> {
-    rx: Receiver<TkrResult>,
+pub struct Tui {
+    rx: FeedRx,
+    cmd_tx: Sender<WsCommand>,
     state: AppState,
     tkr_tabs: TkrTabs,
-    tkr_data: HashMap<String, DataList>,
-    watchlist: &'a Vec<&'a str>,
+    tkr_data: HashMap<MarketKey, DataList>, // every venue, for the compare view
+    book_data: HashMap<String, OrderBook>,
+    trade_data: HashMap<String, TradeList>,
+    candle_data: HashMap<String, Candles>,
+    indicator_data: HashMap<String, Indicators>, // of the chart interval
+    studies: Vec<Study>,                         // shown on the chart
+    interval: Interval,
+    min_qty_step: usize,
+    conn_status: ConnStatus,
+    venues: Vec<String>, // selected venue first
+    venue_links: HashMap<String, LinkState>,
+    refused: HashMap<String, String>, // subscriptions the selected venue turned down
+    alerts: Alerts,
+    hooks: Option<Hooks>,
+    deliveries: VecDeque<Delivery>, // newest first
+    flash: Option<(Fired, Instant)>,
+    positions: Positions,
+    prompt: Option<Prompt>,
+    instruments: Vec<Instrument>,
+    watchlist: Vec<String>,
+    config: Config,
+    hints: String,
 }
 
-impl<'a> Tui<'a> {
-    pub fn new(rx: Receiver<TkrResult>, watchlist: &'a Vec<&'a str>) -> Self {
+impl Tui {
+    pub fn new(
+        rx: FeedRx,
+        cmd_tx: Sender<WsCommand>,
+        watchlist: Vec<String>,
+        config: Config,
+        alerts: Alerts,
+        hooks: Option<Hooks>,
+        positions: Positions,
+    ) -> Self {
+        let keys = &config.keys;
+        let hints = format!(
+            "({}) next ({}) add ({}) remove ({}) trades ({}) book ({}) compare ({}) alerts ({}) portfolio ({}/{}) size filter ({}) indicators ({}) quit",
+            keys.hint(Action::NextTab),
+            keys.hint(Action::Add),
+            keys.hint(Action::Remove),
+            keys.hint(Action::TradesView),
+            keys.hint(Action::BookView),
+            keys.hint(Action::CompareView),
+            keys.hint(Action::AlertsView),
+            keys.hint(Action::PortfolioView),
+            keys.hint(Action::FilterUp),
+            keys.hint(Action::FilterDown),
+            keys.hint(Action::Indicators),
+            keys.hint(Action::Quit),
+        );
         Self {
             rx,
+            cmd_tx,
             state: AppState::Running,
             tkr_tabs: TkrTabs::default(),
             tkr_data: HashMap::new(),
+            book_data: HashMap::new(),
+            trade_data: HashMap::new(),
+            candle_data: HashMap::new(),
+            indicator_data: HashMap::new(),
+            studies: config.indicators.show.clone(),
+            interval: config.chart.interval,
+            min_qty_step: 0,
+            conn_status: ConnStatus::default(),
+            venues: config.endpoint.venues(),
+            venue_links: HashMap::new(),
+            refused: HashMap::new(),
+            alerts,
+            hooks,
+            deliveries: VecDeque::new(),
+            flash: None,
+            positions,
+            prompt: None,
+            instruments: Vec::new(),
             watchlist,
+            config,
+            hints,
         }
     }
 
     pub async fn run(mut self, mut terminal: DefaultTerminal) -> Result<()> {
         info!("Starting Tui");
-        for tkr in self.watchlist {
-            let tkr_data = DataList::new(1_000);
-            self.tkr_data.insert(tkr.to_string(), tkr_data);
+        for tkr in self.watchlist.clone() {
+            self.init_tkr(&tkr);
         }
+        self.watch_positions();
+
+        let mut input = EventStream::new();
+        let mut frame_tick = time::interval(FRAME);
+        frame_tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut dirty = true;
+        let mut last_draw = Instant::now();
 
         while self.state == AppState::Running {
-            match self.rx.try_recv() {
-                Ok(tkr_result) => {
-                    if let Some(data) = self.tkr_data.get_mut(&tkr_result.tkr) {
-                        data.insert(&tkr_result);
+            tokio::select! {
+                Some(event) = self.rx.recv() => {
+                    self.handle_ws(event);
+                    // Catch up on everything queued before the next frame
+                    while let Some(event) = self.rx.try_recv() {
+                        self.handle_ws(event);
+                    }
+                    dirty = true;
+                }
+                Some(event) = input.next() => {
+                    self.handle_input(event?);
+                    dirty = true;
+                }
+                _ = frame_tick.tick() => {
+                    let fired = self.alerts.check_stale(Utc::now().timestamp_millis());
+                    if !fired.is_empty() {
+                        self.alert(fired);
+                        dirty = true;
+                    }
+                    // Ticker ages in the status bar move on even when idle
+                    if dirty || last_draw.elapsed() >= IDLE_REDRAW {
+                        terminal.draw(|frame| self.render(frame))?;
+                        dirty = false;
+                        last_draw = Instant::now();
                     }
-                    info!("tkr_result: {:#?}", tkr_result);
                 }
-                Err(_e) => {}
             }
-            terminal.draw(|frame| self.render(frame))?;
-            self.handle_event()?;
-
-            tokio::task::yield_now().await;
         }
         Ok(())
     }
 
-    fn handle_event(&mut self) -> Result<()> {
-        if event::poll(std::time::Duration::from_millis(16))? {
-            if let event::Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    match key.code {
-                        KeyCode::Char('q') => {
-                            self.state = AppState::Quitting;
-                        }
-                        KeyCode::Char('1') => {
-                            self.tkr_tabs.select(min(1, self.watchlist.len()));
-                        }
-                        KeyCode::Char('2') => {
-                            self.tkr_tabs.select(min(2, self.watchlist.len()));
-                        }
-                        KeyCode::Char('3') => {
-                            self.tkr_tabs.select(min(3, self.watchlist.len()));
-                        }
-                        KeyCode::Char('4') => {
-                            self.tkr_tabs.select(min(4, self.watchlist.len()));
-                        }
-                        KeyCode::Char('5') => {
-                            self.tkr_tabs.select(min(5, self.watchlist.len()));
+    fn handle_ws(&mut self, event: WsEvent) {
+        match event {
+            WsEvent::Tkr(tkr_result) => {
+                let key = MarketKey::new(&tkr_result.venue, &tkr_result.tkr);
+                let selected = self.is_selected(&tkr_result.venue);
+                let Some(data) = self.tkr_data.get_mut(&key) else {
+                    return;
+                };
+                let added = data.insert(&tkr_result);
+                // Alerts watch the selected venue, on every update the feed kept
+                if selected {
+                    let now = Utc::now().timestamp_millis();
+                    let mut fired = Vec::new();
+                    for entry in data.data.iter().rev().take(added).rev() {
+                        fired.extend(self.alerts.check(&tkr_result.tkr, entry, now));
+                    }
+                    self.alert(fired);
+                }
+                info!("tkr_result: {:#?}", tkr_result);
+            }
+            // Books and trades are only shown for the selected venue
+            WsEvent::Book(book_result) if !self.is_selected(&book_result.venue) => {}
+            WsEvent::Trade(trade_result) if !self.is_selected(&trade_result.venue) => {}
+            WsEvent::Book(book_result) => {
+                let Some(book) = self.book_data.get_mut(&book_result.tkr) else {
+                    return;
+                };
+                if book.insert(&book_result) {
+                    debug!("{} book out of sequence, resyncing", book_result.tkr);
+                    self.send_command(WsCommand::ResyncBook(book_result.venue, book_result.tkr));
+                }
+            }
+            WsEvent::Trade(trade_result) => {
+                if let Some(trades) = self.trade_data.get_mut(&trade_result.tkr) {
+                    let accepted = trades.insert(&trade_result);
+                    if let Some(candles) = self.candle_data.get_mut(&trade_result.tkr) {
+                        candles.insert(&accepted);
+                        if let Some(indicators) = self.indicator_data.get_mut(&trade_result.tkr) {
+                            indicators.update(&candles.get(self.interval).candles);
                         }
-                        _ => {}
                     }
                 }
             }
+            WsEvent::Status(status) => {
+                if !status.venue.is_empty() {
+                    self.venue_links.insert(status.venue.clone(), status.state);
+                }
+                if self.is_selected(&status.venue) {
+                    self.conn_status = status;
+                }
+            }
+            WsEvent::SubAck(ack) => self.handle_ack(ack),
+            WsEvent::Catalogue(instruments) => {
+                self.instruments = instruments;
+            }
+            WsEvent::Delivery(delivery) => {
+                self.deliveries.push_front(delivery);
+                self.deliveries.truncate(DELIVERIES);
+            }
+        }
+    }
+
+    /// Venue of the book, trades and chart; an empty venue is a replay of one
+    fn is_selected(&self, venue: &str) -> bool {
+        venue.is_empty() || venue == self.config.endpoint.venue
+    }
+
+    /// Flashes the newest of `fired` in the header, with the bell if
+    /// configured, and passes each on to the hooks
+    fn alert(&mut self, fired: Vec<Fired>) {
+        if let Some(hooks) = &self.hooks {
+            for event in fired.iter() {
+                hooks.deliver(event);
+            }
+        }
+        let Some(last) = fired.into_iter().last() else {
+            return;
+        };
+        self.flash = Some((last, Instant::now()));
+        if self.config.alerts.bell {
+            let mut out = stdout();
+            if let Err(e) = out.write_all(b"\x07").and_then(|_| out.flush()) {
+                debug!("Bell failed: {}", e);
+            }
+        }
+    }
+
+    fn handle_ack(&mut self, ack: SubAck) {
+        if !self.is_selected(&ack.venue) {
+            if let Some(error) = ack.error {
+                debug!("{} refused {}: {}", ack.venue, ack.tkr, error);
+            }
+            return;
+        }
+        match ack.error {
+            Some(error) if ack.subscribe => {
+                self.refused.insert(ack.tkr, error);
+            }
+            Some(error) => debug!("Unsubscribing {} failed: {}", ack.tkr, error),
+            None => {
+                self.refused.remove(&ack.tkr);
+            }
+        }
+    }
+
+    fn init_tkr(&mut self, tkr: &str) {
+        let buffers = &self.config.buffers;
+        for venue in self.venues.iter() {
+            self.tkr_data
+                .insert(MarketKey::new(venue, tkr), DataList::new(buffers.tickers));
+        }
+        self.book_data.insert(tkr.to_string(), OrderBook::default());
+        self.trade_data
+            .insert(tkr.to_string(), TradeList::new(buffers.trades));
+        self.candle_data
+            .insert(tkr.to_string(), Candles::new(buffers.candles));
+        self.indicator_data.insert(
+            tkr.to_string(),
+            Indicators::new(&self.config.indicators, buffers.candles),
+        );
+    }
+
+    /// Starts the studies over, after the interval or a period changed
+    fn reset_indicators(&mut self) {
+        for (tkr, candles) in self.candle_data.iter() {
+            let mut indicators =
+                Indicators::new(&self.config.indicators, self.config.buffers.candles);
+            indicators.update(&candles.get(self.interval).candles);
+            self.indicator_data.insert(tkr.clone(), indicators);
+        }
+    }
+
+    fn toggle_study(&mut self, study: Study) {
+        if let Some(i) = self.studies.iter().position(|s| *s == study) {
+            self.studies.remove(i);
+        } else {
+            self.studies.push(study);
+        }
+    }
+
+    fn add_tkr(&mut self, tkr: String) {
+        if tkr.is_empty() || self.watchlist.contains(&tkr) {
+            return;
+        }
+        self.watch(tkr);
+        self.tkr_tabs
+            .select(self.watchlist.len() - 1, self.watchlist.len());
+    }
+
+    /// Adds `tkr` to the watchlist and subscribes, leaving the tab as it is
+    fn watch(&mut self, tkr: String) {
+        self.init_tkr(&tkr);
+        self.watchlist.push(tkr.clone());
+        self.send_command(WsCommand::Subscribe(tkr));
+    }
+
+    /// Positions are marked from tickers, so their instruments are watched
+    fn watch_positions(&mut self) {
+        for position in self.positions.positions.clone() {
+            if !self.watchlist.contains(&position.instrument) {
+                self.watch(position.instrument);
+            }
+        }
+    }
+
+    fn remove_tkr(&mut self, tkr: String) {
+        let Some(i) = self.watchlist.iter().position(|t| *t == tkr) else {
+            return;
+        };
+        self.watchlist.remove(i);
+        self.tkr_tabs.remove(i, self.watchlist.len());
+        self.tkr_data.retain(|key, _| key.tkr != tkr);
+        self.refused.remove(&tkr);
+        self.book_data.remove(&tkr);
+        self.trade_data.remove(&tkr);
+        self.candle_data.remove(&tkr);
+        self.indicator_data.remove(&tkr);
+        self.send_command(WsCommand::Unsubscribe(tkr));
+    }
+
+    fn send_command(&self, cmd: WsCommand) {
+        if let Err(e) = self.cmd_tx.try_send(cmd) {
+            debug!("ws_client command dropped: {}", e);
+        }
+    }
+
+    fn handle_prompt(&mut self, key: KeyEvent) {
+        let Some(prompt) = self.prompt.as_mut() else {
+            return;
+        };
+        let matches = match prompt.mode {
+            PromptMode::Add => catalogue::search(&self.instruments, &prompt.input, PICKER_MATCHES),
+            PromptMode::Remove
+            | PromptMode::Indicator
+            | PromptMode::Alert
+            | PromptMode::Position => Vec::new(),
+        };
+        let selected = matches.get(prompt.selected).map(|inst| inst.symbol.clone());
+
+        match key.code {
+            KeyCode::Char(c) => prompt.push(c),
+            KeyCode::Backspace => prompt.pop(),
+            KeyCode::Up => prompt.up(),
+            KeyCode::Down => prompt.down(matches.len()),
+            KeyCode::Tab => {
+                if let Some(symbol) = selected {
+                    prompt.input = symbol;
+                }
+            }
+            KeyCode::Esc => self.prompt = None,
+            KeyCode::Enter => {
+                if let Some(prompt) = self.prompt.take() {
+                    match prompt.mode {
+                        PromptMode::Add => self.add_tkr(selected.unwrap_or(prompt.input)),
+                        PromptMode::Remove => self.remove_tkr(prompt.input),
+                        PromptMode::Indicator => self.set_indicator(prompt),
+                        PromptMode::Alert => self.edit_alerts(prompt),
+                        PromptMode::Position => self.edit_positions(prompt),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies an indicator setting and shows the study, or keeps the
+    /// prompt open with the reason it was rejected
+    fn set_indicator(&mut self, mut prompt: Prompt) {
+        match self.config.indicators.set(&prompt.input) {
+            Ok(study) => {
+                if !self.studies.contains(&study) {
+                    self.studies.push(study);
+                }
+                self.reset_indicators();
+            }
+            Err(e) => {
+                prompt.error = Some(e.to_string());
+                self.prompt = Some(prompt);
+            }
+        }
+    }
+
+    /// Adds the rule typed, or arms, disarms or deletes one by number:
+    /// "on 2", "off 2", "del 2"
+    fn edit_alerts(&mut self, mut prompt: Prompt) {
+        let words: Vec<&str> = prompt.input.split_whitespace().collect();
+        let number = |n: &str| {
+            n.parse::<usize>()
+                .map_err(|_| anyhow!("invalid alert number {:?}", n))
+        };
+        let result = match words.as_slice() {
+            ["on", n] => number(n).and_then(|n| self.alerts.set_armed(n, true)),
+            ["off", n] => number(n).and_then(|n| self.alerts.set_armed(n, false)),
+            ["del", n] => number(n).and_then(|n| self.alerts.remove(n)),
+            _ => self.alerts.add(&prompt.input),
+        };
+        match result {
+            Ok(()) => self.tkr_tabs.set_view(TabView::Alerts),
+            Err(e) => {
+                prompt.error = Some(e.to_string());
+                self.prompt = Some(prompt);
+            }
+        }
+    }
+
+    /// Adds the position typed, or deletes one by number: "del 2"
+    fn edit_positions(&mut self, mut prompt: Prompt) {
+        let words: Vec<&str> = prompt.input.split_whitespace().collect();
+        let result = match words.as_slice() {
+            ["del", n] => n
+                .parse::<usize>()
+                .map_err(|_| anyhow!("invalid position number {:?}", n))
+                .and_then(|n| self.positions.remove(n)),
+            _ => self.positions.add(&prompt.input),
+        };
+        match result {
+            Ok(()) => {
+                self.watch_positions();
+                self.tkr_tabs.set_view(TabView::Portfolio);
+            }
+            Err(e) => {
+                prompt.error = Some(e.to_string());
+                self.prompt = Some(prompt);
+            }
+        }
+    }
+
+    fn handle_input(&mut self, event: Event) {
+        let Event::Key(key) = event else {
+            return; // resizes are picked up by the next draw
+        };
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+        let chord = key
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if self.prompt.is_some() && !chord {
+            self.handle_prompt(key);
+        } else if let (KeyCode::Char(c @ '1'..='9'), false) = (key.code, chord) {
+            let i = c as usize - '1' as usize;
+            self.tkr_tabs.select(i, self.watchlist.len());
+        } else if let Some(action) = self.config.keys.action(key) {
+            self.handle_action(action);
+        }
+    }
+
+    fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::Quit => self.state = AppState::Quitting,
+            Action::Add => self.prompt = Some(Prompt::new(PromptMode::Add, "")),
+            Action::Remove => {
+                let tkr = self.tkr_tabs.selected_tkr(&self.watchlist);
+                self.prompt = Some(Prompt::new(PromptMode::Remove, tkr.unwrap_or_default()));
+            }
+            Action::TradesView => self.tkr_tabs.set_view(TabView::Trades),
+            Action::BookView => self.tkr_tabs.set_view(TabView::Book),
+            Action::CompareView => self.tkr_tabs.set_view(TabView::Compare),
+            Action::NextInterval => {
+                self.interval = self.interval.next();
+                self.reset_indicators();
+            }
+            Action::PrevInterval => {
+                self.interval = self.interval.prev();
+                self.reset_indicators();
+            }
+            Action::FilterUp => {
+                self.min_qty_step = min(self.min_qty_step + 1, MIN_QTY_STEPS.len() - 1);
+            }
+            Action::FilterDown => self.min_qty_step = self.min_qty_step.saturating_sub(1),
+            Action::NextTab => self.tkr_tabs.next(self.watchlist.len()),
+            Action::PrevTab => self.tkr_tabs.prev(self.watchlist.len()),
+            Action::Step => self.send_command(WsCommand::Step),
+            Action::Toggle(study) => self.toggle_study(study),
+            Action::Indicators => self.prompt = Some(Prompt::new(PromptMode::Indicator, "")),
+            Action::AlertsView => self.tkr_tabs.set_view(TabView::Alerts),
+            Action::PortfolioView => self.tkr_tabs.set_view(TabView::Portfolio),
+            Action::Position => {
+                let tkr = self.tkr_tabs.selected_tkr(&self.watchlist);
+                let input = tkr.map(|tkr| format!("{} ", tkr)).unwrap_or_default();
+                self.prompt = Some(Prompt::new(PromptMode::Position, &input));
+            }
+            Action::Alert => {
+                // Starts off with the instrument of the tab
+                let tkr = self.tkr_tabs.selected_tkr(&self.watchlist);
+                let input = tkr.map(|tkr| format!("{} ", tkr)).unwrap_or_default();
+                self.prompt = Some(Prompt::new(PromptMode::Alert, &input));
+            }
         }
-        Ok(())
     }
 
     // fn render(self, area: Rect, buf: &mut Buffer) {
@@ -106,7 +541,7 @@ impl<'a> Tui<'a> {
         ])
         .areas(frame.area());
 
-        let [_title_area, tabs_area] =
+        let [title_area, tabs_area] =
             Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
                 .areas(header_area);
 
@@ -117,21 +552,128 @@ impl<'a> Tui<'a> {
             Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)])
                 .areas(left_area);
 
-        frame.render_widget(Paragraph::new("Crypto Dashboard"), frame.area());
-        frame.render_widget(Paragraph::new("Press (q) to quit..."), frame.area());
-        frame.render_widget(summary::Summary::new(&self.tkr_data), top_left_area);
+        match &self.flash {
+            Some((fired, at)) if at.elapsed() < FLASH => {
+                let text = format!(" ALERT {} ({}) ", fired.rule, fired.value);
+                let theme = &self.config.theme;
+                // Blinks with the idle redraw
+                let span = if at.elapsed().as_secs() % 2 == 0 {
+                    Span::from(text).bg(theme.warn).fg(theme.highlight_fg)
+                } else {
+                    Span::from(text).fg(theme.warn)
+                };
+                frame.render_widget(Line::from(span), title_area);
+            }
+            _ => frame.render_widget(Paragraph::new("Crypto Dashboard"), title_area),
+        }
+        match &self.prompt {
+            Some(prompt) => frame.render_widget(prompt.widget(&self.config.theme), footer_area),
+            None => frame.render_widget(
+                StatusBar::new(
+                    &self.conn_status,
+                    &self.watchlist,
+                    &self.refused,
+                    self.rx.dropped(),
+                    &self.config.theme,
+                    &self.hints,
+                ),
+                footer_area,
+            ),
+        }
         frame.render_widget(
-            chart::TkrChart::new(
-                &self.tkr_data[self.watchlist[self.tkr_tabs.selected_tab as usize]],
+            summary::Summary::new(
+                &self.tkr_data,
+                &self.config.endpoint.venue,
+                &self.config.theme,
             ),
-            right_area,
+            top_left_area,
         );
         frame.render_widget(self.tkr_tabs.widget(&self.watchlist), tabs_area);
-        frame.render_widget(
-            self.tkr_tabs
-                .selected_tab
-                .widget_trades(&self.tkr_data, &self.watchlist),
-            bottom_left_area,
-        );
+
+        if let Some(tkr) = self.tkr_tabs.selected_tkr(&self.watchlist) {
+            self.render_tkr(frame, tkr, right_area, bottom_left_area);
+        }
+        // Last, so the matches are drawn over the panes
+        self.render_picker(frame, main_area);
+    }
+
+    /// Chart and the bottom left pane of the selected instrument
+    fn render_tkr(&self, frame: &mut Frame, tkr: &str, chart_area: Rect, pane_area: Rect) {
+        if let Some(candles) = self.candle_data.get(tkr) {
+            frame.render_widget(
+                chart::TkrChart::new(
+                    candles.get(self.interval),
+                    self.indicator_data.get(tkr),
+                    &self.studies,
+                    &self.config.indicators,
+                    &self.config.theme,
+                ),
+                chart_area,
+            );
+        }
+        match self.tkr_tabs.view() {
+            TabView::Trades => {
+                if let Some(tape) = self.tkr_tabs.widget_trades(
+                    &self.trade_data,
+                    &self.watchlist,
+                    MIN_QTY_STEPS[self.min_qty_step],
+                    &self.config.theme,
+                ) {
+                    frame.render_widget(tape, pane_area);
+                }
+            }
+            TabView::Book => {
+                if let Some(ladder) =
+                    self.tkr_tabs
+                        .widget_book(&self.book_data, &self.watchlist, &self.config.theme)
+                {
+                    frame.render_widget(ladder, pane_area);
+                }
+            }
+            TabView::Alerts => frame.render_widget(
+                AlertLog::new(&self.alerts, &self.deliveries, &self.config.theme),
+                pane_area,
+            ),
+            TabView::Portfolio => frame.render_widget(
+                Portfolio::new(
+                    &self.positions.positions,
+                    &self.tkr_data,
+                    &self.config.endpoint.venue,
+                    &self.instruments,
+                    &self.config.theme,
+                ),
+                pane_area,
+            ),
+            TabView::Compare => frame.render_widget(
+                VenueComparison::new(
+                    tkr,
+                    &self.venues,
+                    &self.tkr_data,
+                    &self.venue_links,
+                    &self.config.theme,
+                ),
+                pane_area,
+            ),
+        }
+    }
+
+    /// Draws autocomplete matches along the bottom of `area` while adding
+    fn render_picker(&self, frame: &mut Frame, area: Rect) {
+        let Some(prompt) = self.prompt.as_ref() else {
+            return;
+        };
+        if prompt.mode != PromptMode::Add || self.instruments.is_empty() {
+            return;
+        }
+        let matches = catalogue::search(&self.instruments, &prompt.input, PICKER_MATCHES);
+        let picker = InstrumentPicker::new(&matches, prompt.selected, &self.config.theme);
+        let height = picker.height().min(area.height);
+        let picker_area = Rect {
+            y: area.y + area.height - height,
+            height,
+            width: area.width.min(72),
+            ..area
+        };
+        frame.render_widget(picker, picker_area);
     }
 }
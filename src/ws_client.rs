@@ -1,106 +1,594 @@
-use crate::models::{TkrResponse, TkrResult};
+use crate::crash;
+use crate::feed::FeedTx;
+use crate::models::{ConnStatus, LinkState, WsCommand, WsEvent};
+use crate::recording::Recorder;
+use crate::sources::{Decoded, MarketDataSource, Protocol};
 use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use cli_log::*;
 use futures_util::{SinkExt, StreamExt};
-use serde::{Deserialize, Serialize};
-use serde_json;
+use rand::Rng;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::Receiver;
+use tokio::time::{interval, sleep, timeout};
 use tokio_tungstenite::{
     connect_async, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream,
 };
 
-#[derive(Deserialize)]
-struct Heartbeat {
-    id: u64,
-    // method: String,
-    // code: i64,
+// crypto.com sends a heartbeat every 30s, so a quiet link this long is dead
+const STALE_TIMEOUT: Duration = Duration::from_secs(65);
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+// A server that takes the connection but never answers the upgrade
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Exponential backoff with equal jitter: half of each delay is fixed, the
+/// other half random, so clients dropped together don't reconnect together.
+struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Self { attempt: 0 }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let exp = BACKOFF_BASE.saturating_mul(2u32.saturating_pow(self.attempt));
+        let ceiling = exp.min(BACKOFF_MAX);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let half = ceiling / 2;
+        half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+    }
+
+    fn reset(&mut self) {
+        self.attempt = 0;
+    }
 }
 
-pub struct WsClient<'a> {
-    url: &'a str,
-    tx: Sender<TkrResult>,
-    watchlist: Vec<&'a str>,
+/// Link metrics collected while streaming, reported to the Tui as ConnStatus
+struct LinkStats {
+    state: LinkState,
+    last_recv: Instant,
+    last_msg: HashMap<String, Instant>,
+    msg_count: u64,
+    window_start: Instant,
+    ping_sent: Option<Instant>,
+    rtt: Option<Duration>,
+    reconnects: u32,
 }
 
-impl<'a> WsClient<'a> {
-    pub fn new(url: &'a str, tx: Sender<TkrResult>, watchlist: Vec<&'a str>) -> Self {
-        Self { url, tx, watchlist }
+impl LinkStats {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            state: LinkState::Connected,
+            last_recv: now,
+            last_msg: HashMap::new(),
+            msg_count: 0,
+            window_start: now,
+            ping_sent: None,
+            rtt: None,
+            reconnects: 0,
+        }
+    }
+
+    fn on_connected(&mut self) {
+        let now = Instant::now();
+        self.state = LinkState::Connected;
+        self.last_recv = now;
+        self.msg_count = 0;
+        self.window_start = now;
+        self.ping_sent = None;
     }
 
-    pub async fn connect(self) -> Result<WsClientConnected<'a>> {
-        let (ws_stream, _resp) = connect_async(self.url).await?;
+    /// Builds a status snapshot and starts a new message rate window
+    fn snapshot(&mut self, venue: &str) -> ConnStatus {
+        let elapsed = self.window_start.elapsed().as_secs_f64();
+        let msg_rate = if elapsed > 0.0 {
+            self.msg_count as f64 / elapsed
+        } else {
+            0.0
+        };
+        self.msg_count = 0;
+        self.window_start = Instant::now();
 
-        Ok(WsClientConnected {
-            url: self.url,
-            tx: self.tx,
-            watchlist: self.watchlist,
-            ws_stream,
-        })
+        ConnStatus {
+            venue: venue.to_string(),
+            state: self.state,
+            last_msg: self.last_msg.clone(),
+            rtt: self.rtt,
+            msg_rate,
+            reconnects: self.reconnects,
+        }
     }
 }
 
-pub struct WsClientConnected<'a> {
-    url: &'a str,
-    tx: Sender<TkrResult>,
-    watchlist: Vec<&'a str>,
-    ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+/// Websocket session shared by every venue: subscriptions, reconnects
+/// with backoff, link stats and recording. `P` speaks the venue's format.
+pub struct WsClient<P: Protocol> {
+    url: String,
+    protocol: P,
+    tx: FeedTx,
+    cmd_rx: Receiver<WsCommand>,
+    watchlist: Vec<String>,
+    recorder: Option<Recorder>,
+    ws_stream: Option<WsStream>,
+    connect_timeout: Duration,
+    backoff: Backoff,
+    stats: LinkStats,
 }
 
-impl<'a> WsClientConnected<'a> {
-    pub async fn run(&mut self) -> Result<()> {
-        debug!("Connected ws_client");
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Opens the websocket, giving up on a handshake that takes over `limit`
+async fn open(url: &str, limit: Duration) -> Result<WsStream> {
+    let (ws_stream, _resp) = timeout(limit, connect_async(url))
+        .await
+        .map_err(|_| anyhow!("no handshake within {}s", limit.as_secs_f64()))??;
+    Ok(ws_stream)
+}
+
+impl<P: Protocol> WsClient<P> {
+    pub fn new(
+        url: &str,
+        protocol: P,
+        tx: FeedTx,
+        cmd_rx: Receiver<WsCommand>,
+        watchlist: Vec<String>,
+    ) -> Self {
+        Self {
+            url: url.to_string(),
+            protocol,
+            tx,
+            cmd_rx,
+            watchlist,
+            recorder: None,
+            ws_stream: None,
+            connect_timeout: CONNECT_TIMEOUT,
+            backoff: Backoff::new(),
+            stats: LinkStats::new(),
+        }
+    }
+
+    /// Writes every text frame received to `recorder`
+    pub fn record(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    // A failing recorder is dropped rather than taking the live feed down
+    fn record_frame(&mut self, text: &str) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.write(self.protocol.venue(), text) {
+                debug!("Recording stopped: {}", e);
+                self.recorder = None;
+            }
+        }
+    }
+
+    fn flush_recording(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.flush() {
+                debug!("Recording stopped: {}", e);
+                self.recorder = None;
+            }
+        }
+    }
+
+    async fn run_streams(&mut self) -> Result<()> {
+        loop {
+            match self.stream().await {
+                Ok(()) => debug!("ws_client stream closed"),
+                Err(e) => debug!("ws_client stream error: {}", e),
+            }
+            if self.tx.is_closed() {
+                return Ok(());
+            }
+            self.stats.state = LinkState::Disconnected;
+            self.send_status().await;
+            if !self.reconnect().await {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn stream(&mut self) -> Result<()> {
+        debug!("Connected ws_client to {}", self.protocol.venue());
+        self.stats.on_connected();
+        self.send_status().await;
 
         self.subscribe_tkr().await?;
 
-        while let Some(msg) = self.ws_stream.next().await {
+        let mut ping_timer = interval(PING_INTERVAL);
+        let mut status_timer = interval(STATUS_INTERVAL);
+        loop {
+            let Some(ws_stream) = self.ws_stream.as_mut() else {
+                return Err(anyhow!("not connected"));
+            };
+            let msg = tokio::select! {
+                _ = self.tx.closed() => return Ok(()),
+                Some(cmd) = self.cmd_rx.recv() => {
+                    self.handle_command(cmd).await?;
+                    continue;
+                }
+                _ = ping_timer.tick() => {
+                    self.ping().await?;
+                    continue;
+                }
+                _ = status_timer.tick() => {
+                    if self.stats.last_recv.elapsed() > STALE_TIMEOUT {
+                        return Err(anyhow!("no message for {:?}", STALE_TIMEOUT));
+                    }
+                    self.flush_recording();
+                    self.send_status().await;
+                    continue;
+                }
+                msg = ws_stream.next() => match msg {
+                    Some(msg) => msg,
+                    None => return Ok(()),
+                },
+            };
+            self.stats.last_recv = Instant::now();
+
             match msg {
                 Ok(Message::Text(text)) => {
-                    let json_value: serde_json::Value = serde_json::from_str(&text)?;
-
-                    let method = json_value.get("method").expect("none");
-                    if method == "subscribe" {
-                        let tkr_resp: TkrResponse = serde_json::from_value(json_value)?;
-                        // debug!("{:#?}", tkr_resp);
-                        self.tx.send(tkr_resp.result).await?;
-                    } else if method == "public/heartbeat" {
-                        let heartbeat: Heartbeat = serde_json::from_value(json_value)?;
-                        self.heartbeat_response(heartbeat.id).await?;
-                    } else {
-                        debug!("Unmatched json: {:#?}", json_value);
+                    // Data means the link is back; one dropped right after
+                    // the handshake keeps backing off
+                    self.backoff.reset();
+                    self.stats.msg_count += 1;
+                    self.record_frame(&text);
+                    self.handle_text(&text).await?;
+                }
+                Ok(Message::Pong(_)) => {
+                    if let Some(sent) = self.stats.ping_sent.take() {
+                        self.stats.rtt = Some(sent.elapsed());
                     }
                 }
+                Ok(Message::Close(frame)) => {
+                    debug!("Close frame: {:?}", frame);
+                    return Ok(());
+                }
                 Err(e) => return Err(anyhow!(e)),
-                _ => {} // binary, pong, ping, etc
+                _ => {} // binary, ping, etc
+            }
+        }
+    }
+
+    async fn handle_text(&mut self, text: &str) -> Result<()> {
+        crash::remember(self.protocol.venue(), text);
+        // A frame we can't read is dropped, the connection is still good
+        let decoded = match self.protocol.decode(text) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                debug!("Malformed frame ({}): {}", e, text);
+                return Ok(());
             }
+        };
+        match decoded {
+            Decoded::Event(event) => {
+                if let WsEvent::Tkr(result) = &event {
+                    self.stats
+                        .last_msg
+                        .insert(result.tkr.clone(), Instant::now());
+                }
+                self.tx.send(event)?;
+            }
+            Decoded::Events(events) => {
+                for event in events {
+                    self.tx.send(event)?;
+                }
+            }
+            Decoded::Reply(reply) => self.send_text(reply).await?,
+            Decoded::Ignore => {}
         }
         Ok(())
     }
 
+    async fn handle_command(&mut self, cmd: WsCommand) -> Result<()> {
+        let frames = match &cmd {
+            WsCommand::Subscribe(tkr) if !self.watchlist.contains(tkr) => {
+                self.protocol.subscribe(tkr)
+            }
+            WsCommand::Unsubscribe(tkr) if self.watchlist.contains(tkr) => {
+                self.protocol.unsubscribe(tkr)
+            }
+            WsCommand::ResyncBook(venue, tkr)
+                if venue == self.protocol.venue() && self.watchlist.contains(tkr) =>
+            {
+                self.protocol.resync_book(tkr)
+            }
+            _ => Vec::new(),
+        };
+        // Watchlist first, so a send lost to a drop is made good by the
+        // resubscribe after reconnecting
+        self.apply(cmd);
+        for frame in frames {
+            self.send_text(frame).await?;
+        }
+        Ok(())
+    }
+
+    /// Keeps the watchlist in step with a command
+    fn apply(&mut self, cmd: WsCommand) {
+        match cmd {
+            WsCommand::Subscribe(tkr) => {
+                if !self.watchlist.contains(&tkr) {
+                    self.watchlist.push(tkr);
+                }
+            }
+            WsCommand::Unsubscribe(tkr) => {
+                self.watchlist.retain(|t| *t != tkr);
+                self.stats.last_msg.remove(&tkr);
+            }
+            // Nothing to remember; a reconnect resubscribes books anyway
+            WsCommand::ResyncBook(..) => {}
+            WsCommand::Step => {} // only meaningful when replaying
+        }
+    }
+
+    /// Retries the connection with backoff. Returns false if the receiving
+    /// side hung up while waiting.
+    async fn reconnect(&mut self) -> bool {
+        self.protocol.disconnected();
+        loop {
+            self.stats.state = LinkState::Reconnecting(self.backoff.attempt + 1);
+            self.send_status().await;
+
+            let delay = self.backoff.next_delay();
+            debug!("Reconnecting in {:?}", delay);
+            if self.while_disconnected(sleep(delay)).await.is_none() {
+                return false;
+            }
+
+            let url = self.url.clone();
+            match self
+                .while_disconnected(open(&url, self.connect_timeout))
+                .await
+            {
+                None => return false,
+                Some(Ok(ws_stream)) => {
+                    self.ws_stream = Some(ws_stream);
+                    self.stats.reconnects += 1;
+                    return true;
+                }
+                Some(Err(e)) => debug!("Reconnect failed: {}", e),
+            }
+        }
+    }
+
+    /// Awaits `fut` while applying commands to the watchlist, so they are
+    /// neither lost nor left to fill the channel until the link is back.
+    /// None if the receiving side hung up meanwhile.
+    async fn while_disconnected<F: Future>(&mut self, fut: F) -> Option<F::Output> {
+        tokio::pin!(fut);
+        loop {
+            tokio::select! {
+                _ = self.tx.closed() => return None,
+                output = &mut fut => return Some(output),
+                Some(cmd) = self.cmd_rx.recv() => self.apply(cmd),
+            }
+        }
+    }
+
     async fn subscribe_tkr(&mut self) -> Result<()> {
-        for tkr in self.watchlist.iter() {
-            let tkr_sub = serde_json::json!({
-                "id": 1,
-                "method": "subscribe",
-                "params": {"channels": [format!("ticker.{}", tkr)]},
-                "nonce": 1000,
-            });
-            self.ws_stream
-                .send(Message::Text(tkr_sub.to_string()))
-                .await?;
+        let watchlist = self.watchlist.clone();
+        for frame in self.protocol.subscribe_all(&watchlist) {
+            self.send_text(frame).await?;
         }
+        Ok(())
+    }
 
+    async fn send_text(&mut self, text: String) -> Result<()> {
+        let ws_stream = self
+            .ws_stream
+            .as_mut()
+            .ok_or_else(|| anyhow!("not connected"))?;
+        ws_stream.send(Message::Text(text)).await?;
         Ok(())
     }
 
-    async fn heartbeat_response(&mut self, id: u64) -> Result<()> {
-        let heartbeat = serde_json::json!({
-            "id": id,
-            "method": "public/respond-heartbeat",
-        });
-        self.ws_stream
-            .send(Message::Text(heartbeat.to_string()))
-            .await?;
+    async fn ping(&mut self) -> Result<()> {
+        // Only time one ping at a time; a lost pong is caught by STALE_TIMEOUT
+        if self.stats.ping_sent.is_none() {
+            if let Some(ws_stream) = self.ws_stream.as_mut() {
+                ws_stream.send(Message::Ping(Vec::new())).await?;
+                self.stats.ping_sent = Some(Instant::now());
+            }
+        }
+        Ok(())
+    }
+
+    async fn send_status(&mut self) {
+        let status = self.stats.snapshot(self.protocol.venue());
+        // a closed channel is picked up by the stream loop
+        let _ = self.tx.send(WsEvent::Status(status));
+    }
+}
+
+#[async_trait]
+impl<P: Protocol> MarketDataSource for WsClient<P> {
+    async fn connect(&mut self) -> Result<()> {
+        self.ws_stream = Some(open(&self.url, self.connect_timeout).await?);
         Ok(())
     }
+
+    /// Streams until the receiving side hangs up, reconnecting and
+    /// resubscribing the watchlist whenever the link drops.
+    async fn run(&mut self) -> Result<()> {
+        if self.ws_stream.is_none() {
+            self.connect().await?;
+        }
+        self.run_streams().await?;
+        self.close()
+    }
+
+    fn close(&mut self) -> Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::cryptocom::CryptoCom;
+    use crate::sources::mock::{event, status, MockConn, MockServer};
+    use tokio::time::timeout;
+
+    const TICKER: &str = r#"{"id":-1,"method":"subscribe","code":0,"result":{"instrument_name":"BTCUSD-PERP","subscription":"ticker.BTCUSD-PERP","channel":"ticker","data":[{"a":"51174.5","i":"BTCUSD-PERP","t":1613580710768}]}}"#;
+
+    /// Instruments of the subscribe frames the client sends on connecting
+    async fn subscribed(conn: &mut MockConn, n: usize) -> Vec<String> {
+        let mut tkrs = Vec::new();
+        for _ in 0..n {
+            let sub = conn.recv().await;
+            assert_eq!(sub["method"], "subscribe");
+            let channel = sub["params"]["channels"][0].as_str().unwrap();
+            tkrs.push(channel.trim_start_matches("ticker.").to_string());
+        }
+        tkrs
+    }
+
+    async fn reconnecting(rx: &mut crate::feed::FeedRx) -> ConnStatus {
+        loop {
+            let status = status(rx).await;
+            if let LinkState::Reconnecting(_) = status.state {
+                return status;
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_grows_with_jitter_up_to_the_cap() {
+        let mut backoff = Backoff::new();
+        for attempt in 0..10 {
+            let ceiling = BACKOFF_BASE.saturating_mul(1 << attempt).min(BACKOFF_MAX);
+            let delay = backoff.next_delay();
+            assert!(delay >= ceiling / 2 && delay <= ceiling, "{:?}", delay);
+        }
+        backoff.reset();
+        assert!(backoff.next_delay() <= BACKOFF_BASE);
+    }
+
+    #[tokio::test]
+    async fn resubscribes_the_watchlist_after_a_drop() {
+        let mut server = MockServer::start().await;
+        let (mut rx, cmd_tx) = server.client(CryptoCom::new(), &["BTCUSD-PERP"]);
+
+        let mut conn = server.accept().await;
+        assert_eq!(subscribed(&mut conn, 1).await, ["BTCUSD-PERP"]);
+        cmd_tx
+            .send(WsCommand::Subscribe("ETHUSD-PERP".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(subscribed(&mut conn, 1).await, ["ETHUSD-PERP"]);
+        conn.send(TICKER).await;
+        drop(conn);
+        assert_eq!(
+            reconnecting(&mut rx).await.state,
+            LinkState::Reconnecting(1)
+        );
+
+        // Instruments added while connected are part of the watchlist now
+        let mut conn = server.accept().await;
+        assert_eq!(
+            subscribed(&mut conn, 2).await,
+            ["BTCUSD-PERP", "ETHUSD-PERP"]
+        );
+        assert_eq!(status(&mut rx).await.state, LinkState::Connected);
+        drop(conn);
+        // Nothing arrived on that connection, so the backoff carries on
+        assert_eq!(
+            reconnecting(&mut rx).await.state,
+            LinkState::Reconnecting(2)
+        );
+
+        let mut conn = server.accept().await;
+        assert_eq!(subscribed(&mut conn, 2).await.len(), 2);
+        conn.send(TICKER).await;
+        drop(conn);
+        let status = reconnecting(&mut rx).await;
+        assert_eq!(status.state, LinkState::Reconnecting(1));
+        assert_eq!(status.reconnects, 2);
+    }
+
+    #[tokio::test]
+    async fn forgets_requests_sent_before_a_drop() {
+        let mut server = MockServer::start().await;
+        let (mut rx, _cmd_tx) = server.client(CryptoCom::new(), &["BTCUSD-PERP"]);
+        let mut conn = server.accept().await;
+        let lost = conn.recv().await;
+        drop(conn);
+
+        let mut conn = server.accept().await;
+        let sub = conn.recv().await;
+        assert_ne!(sub["id"], lost["id"]);
+        for id in [&lost["id"], &sub["id"]] {
+            conn.send(&format!(r#"{{"id":{},"method":"subscribe","code":0}}"#, id))
+                .await;
+        }
+        // Only the request made on this connection is answered
+        assert!(matches!(event(&mut rx).await, WsEvent::SubAck(_)));
+        assert!(timeout(Duration::from_millis(300), event(&mut rx))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn takes_commands_while_reconnecting() {
+        let mut server = MockServer::start().await;
+        let (mut rx, cmd_tx) = server.client(CryptoCom::new(), &["BTCUSD-PERP"]);
+        let mut conn = server.accept().await;
+        subscribed(&mut conn, 1).await;
+        drop(conn);
+        reconnecting(&mut rx).await;
+
+        // More than the channel holds, each taken well within the backoff
+        let tkrs: Vec<String> = (0..20).map(|i| format!("TKR{}-PERP", i)).collect();
+        let mut cmds = vec![WsCommand::Unsubscribe("BTCUSD-PERP".to_string())];
+        cmds.extend(tkrs.iter().cloned().map(WsCommand::Subscribe));
+        for cmd in cmds {
+            timeout(Duration::from_millis(100), cmd_tx.send(cmd))
+                .await
+                .expect("command not taken while reconnecting")
+                .unwrap();
+        }
+
+        let mut conn = server.accept().await;
+        assert_eq!(subscribed(&mut conn, 20).await, tkrs);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_a_silent_handshake() {
+        let mut server = MockServer::start().await;
+        let (tx, mut rx) = crate::feed::channel();
+        let (_cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(16);
+        let watchlist = vec!["BTCUSD-PERP".to_string()];
+        let mut client = WsClient::new(&server.url, CryptoCom::new(), tx, cmd_rx, watchlist);
+        client.connect_timeout = Duration::from_millis(200);
+        tokio::spawn(async move { client.run().await });
+
+        let conn = server.accept().await;
+        server.answer(false);
+        drop(conn);
+        // Each attempt that hangs counts as failed and the next one follows
+        for attempt in 1..=3 {
+            assert_eq!(
+                reconnecting(&mut rx).await.state,
+                LinkState::Reconnecting(attempt)
+            );
+        }
+        server.answer(true);
+        let mut conn = server.accept().await;
+        assert_eq!(subscribed(&mut conn, 1).await, ["BTCUSD-PERP"]);
+    }
 }